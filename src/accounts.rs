@@ -0,0 +1,141 @@
+use std::{fs, path::PathBuf, sync::Arc};
+
+use spacedust::apis::configuration::Configuration;
+
+use crate::{
+    alerts::Alerts,
+    configuration::{optional_var, ConfigurationFactory},
+    credentials::Credentials,
+    database::Database,
+    events::EventLog,
+    fleet::Roster,
+    strategy::Strategy,
+};
+
+/// One of the agents we play and where it keeps its data.
+///
+/// With `AGENTS` set to a comma-separated list of agent symbols, each agent keeps its files in
+/// `agents/<symbol>/` and reads its settings from variables suffixed with `_<symbol>`, like
+/// `TOKEN_MXZ`. Without it, a single agent keeps its files where the unsuffixed variables say.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+    pub name: String,
+    directory: Option<PathBuf>,
+}
+
+impl Account {
+    pub fn all_from_env() -> Vec<Self> {
+        match optional_var("AGENTS") {
+            None => vec![Self {
+                name: optional_var("AGENT_SYMBOL").unwrap_or("MAIN".to_owned()),
+                directory: None,
+            }],
+            Some(agents) => Self::named(&agents),
+        }
+    }
+
+    fn named(agents: &str) -> Vec<Self> {
+        agents
+            .split(',')
+            .map(|a| a.trim().to_ascii_uppercase())
+            .filter(|a| !a.is_empty())
+            .map(|name| Self {
+                directory: Some(PathBuf::from("agents").join(&name)),
+                name,
+            })
+            .collect()
+    }
+
+    /// An agent keeping its files in the directory, for tests that mustn't touch the real ones.
+    #[cfg(test)]
+    pub fn in_directory(name: &str, directory: PathBuf) -> Self {
+        Self {
+            name: name.to_owned(),
+            directory: Some(directory),
+        }
+    }
+
+    /// The agent's setting for `key`.
+    pub fn var(&self, key: &str) -> Option<String> {
+        match &self.directory {
+            None => optional_var(key),
+            Some(_) => optional_var(&format!("{key}_{}", self.name)),
+        }
+    }
+
+    /// The agent symbol to register, the account's name when several agents are played.
+    pub fn agent_symbol(&self) -> Option<String> {
+        match &self.directory {
+            None => optional_var("AGENT_SYMBOL"),
+            Some(_) => Some(self.name.to_owned()),
+        }
+    }
+
+    /// Where the agent keeps the given file. A single agent's can be moved with `key`.
+    pub fn path(&self, key: &str, file_name: &str) -> PathBuf {
+        match &self.directory {
+            None => optional_var(key).unwrap_or(file_name.to_owned()).into(),
+            Some(directory) => {
+                fs::create_dir_all(directory).unwrap();
+                directory.join(file_name)
+            }
+        }
+    }
+
+    pub fn credentials_path(&self) -> PathBuf {
+        self.path("CREDENTIALS_FILE", "credentials.json")
+    }
+
+    /// Opens the agent's database and an API configuration with its own rate limiter, using the
+    /// token of the agent registered last or the one given in `TOKEN`.
+    pub fn connect(&self) -> Session {
+        let token = Credentials::load(&self.credentials_path())
+            .map(|c| c.token)
+            .or_else(|| self.var("TOKEN"))
+            .unwrap_or_else(|| panic!("No credentials saved and no token set for {}", self.name));
+        let database_path = self.path("DATABASE_PATH", "universe.db");
+
+        Session {
+            account: self.clone(),
+            alerts: Alerts::from_env(self),
+            configuration: Arc::new(ConfigurationFactory::get_config(&self.name, &token)),
+            database: Database::open(&database_path.to_string_lossy()),
+            events: EventLog::open(self.path("EVENT_LOG", "events.jsonl")),
+            roster: Roster::new(),
+            strategy: Strategy::from_env(self),
+        }
+    }
+}
+
+/// What the clients of one agent share: its API configuration, its database, its event log, its
+/// alerts, the roles of its ships and its strategy settings.
+#[derive(Clone)]
+pub struct Session {
+    pub account: Account,
+    pub alerts: Alerts,
+    pub configuration: Arc<Configuration>,
+    pub database: Database,
+    pub events: EventLog,
+    pub roster: Roster,
+    pub strategy: Strategy,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_agents_keep_their_own_files() {
+        let accounts = Account::named("mxz, Alpha,");
+
+        assert_eq!(
+            accounts.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(),
+            vec!["MXZ", "ALPHA"]
+        );
+        assert_eq!(accounts[1].agent_symbol(), Some("ALPHA".to_owned()));
+        assert_eq!(
+            accounts[1].directory,
+            Some(PathBuf::from("agents").join("ALPHA"))
+        );
+    }
+}
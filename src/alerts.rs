@@ -0,0 +1,484 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::info;
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+use spacedust::models::{Contract, GetStatus200Response};
+use tokio::time::{interval, Instant};
+
+use crate::{
+    accounts::{Account, Session},
+    client::{parse_time, Client},
+    configuration::parse_var,
+    scheduler::SchedulerHandle,
+    shutdown::Shutdown,
+};
+
+/// API errors further apart than this don't count as repeated.
+const ERROR_WINDOW: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    ShipIdle,
+    ApiErrors,
+    Credits,
+    ContractDeadline,
+    ResetAnnounced,
+}
+
+impl AlertKind {
+    const ALL: [AlertKind; 5] = [
+        AlertKind::ShipIdle,
+        AlertKind::ApiErrors,
+        AlertKind::Credits,
+        AlertKind::ContractDeadline,
+        AlertKind::ResetAnnounced,
+    ];
+
+    /// Parses a kind from its name in `ALERT_EVENTS`, like `ship_idle`.
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| {
+            serde_json::to_value(k).unwrap().as_str() == Some(&name.trim().to_ascii_lowercase())
+        })
+    }
+}
+
+/// Something notable that happened to an agent.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub time: String,
+    pub agent: String,
+    pub kind: AlertKind,
+    pub message: String,
+}
+
+/// Where alerts are delivered.
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn send(&self, alert: &Alert) -> Result<(), String>;
+}
+
+/// Prints alerts to standard output, outside of the log.
+pub struct StdoutSink;
+
+#[async_trait]
+impl AlertSink for StdoutSink {
+    async fn send(&self, alert: &Alert) -> Result<(), String> {
+        println!(
+            "[ALERT] {} {:?}: {}",
+            alert.agent, alert.kind, alert.message
+        );
+        Ok(())
+    }
+}
+
+/// Appends alerts to a JSON lines file.
+pub struct FileSink {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl AlertSink for FileSink {
+    async fn send(&self, alert: &Alert) -> Result<(), String> {
+        let mut line = serde_json::to_string(alert).unwrap();
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// POSTs alerts as JSON to a URL, like a chat webhook.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for WebhookSink {
+    async fn send(&self, alert: &Alert) -> Result<(), String> {
+        self.client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(alert).unwrap())
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// When to alert.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlertSettings {
+    pub kinds: HashSet<AlertKind>,
+    /// How long a ship can go without anything to do.
+    pub idle: chrono::Duration,
+    /// How many API errors within ten minutes are worth an alert.
+    pub api_errors: usize,
+    /// Alerts when our credits go above or below this.
+    pub credits: Option<i64>,
+    /// How close to a contract's deadline it has to be delivered.
+    pub contract_deadline: chrono::Duration,
+}
+
+impl AlertSettings {
+    /// The settings from the agent's `ALERT_EVENTS` (all kinds by default), `ALERT_IDLE_MINUTES`,
+    /// `ALERT_API_ERRORS`, `ALERT_CREDITS` and `ALERT_CONTRACT_HOURS`. Values that don't parse
+    /// are logged and left out.
+    fn from_env(account: &Account) -> Self {
+        let var = |key: &str| parse_var(key, account.var(key));
+
+        let kinds = match account.var("ALERT_EVENTS") {
+            None => AlertKind::ALL.into_iter().collect(),
+            Some(names) => names
+                .split(',')
+                .filter(|n| !n.trim().is_empty())
+                .filter_map(|n| {
+                    let kind = AlertKind::from_name(n);
+                    if kind.is_none() {
+                        info!("[ALERTS] Ignoring unknown alert {n} in ALERT_EVENTS");
+                    }
+                    kind
+                })
+                .collect(),
+        };
+
+        Self {
+            kinds,
+            idle: chrono::Duration::minutes(var("ALERT_IDLE_MINUTES").unwrap_or(30)),
+            api_errors: var("ALERT_API_ERRORS").unwrap_or(5) as usize,
+            credits: var("ALERT_CREDITS"),
+            contract_deadline: chrono::Duration::hours(var("ALERT_CONTRACT_HOURS").unwrap_or(6)),
+        }
+    }
+}
+
+/// Sends an agent's alerts to its sinks, keeping what it needs to tell when something becomes
+/// worth an alert.
+#[derive(Clone)]
+pub struct Alerts {
+    agent: String,
+    sinks: Arc<Vec<Box<dyn AlertSink>>>,
+    settings: AlertSettings,
+    errors: Arc<Mutex<VecDeque<Instant>>>,
+    above_credits: Arc<Mutex<Option<bool>>>,
+    announcements: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Alerts {
+    pub fn new(agent: &str, sinks: Vec<Box<dyn AlertSink>>, settings: AlertSettings) -> Self {
+        Self {
+            agent: agent.to_owned(),
+            sinks: Arc::new(sinks),
+            settings,
+            errors: Arc::default(),
+            above_credits: Arc::default(),
+            announcements: Arc::default(),
+        }
+    }
+
+    /// The sinks listed in the agent's `ALERT_SINKS`, like `stdout,file:alerts.jsonl,
+    /// webhook:https://example.com/hook`. No alerts are sent without it. Sinks it doesn't know
+    /// are logged and left out.
+    pub fn from_env(account: &Account) -> Self {
+        let sinks = (account.var("ALERT_SINKS").unwrap_or_default().split(','))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|sink| -> Option<Box<dyn AlertSink>> {
+                match sink.split_once(':') {
+                    None if sink == "stdout" => Some(Box::new(StdoutSink)),
+                    Some(("file", path)) => Some(Box::new(FileSink { path: path.into() })),
+                    Some(("webhook", url)) => Some(Box::new(WebhookSink::new(url))),
+                    _ => {
+                        info!("[ALERTS] Ignoring unknown alert sink {sink} in ALERT_SINKS");
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        Self::new(&account.name, sinks, AlertSettings::from_env(account))
+    }
+
+    /// Whether alerts of the kind are sent anywhere.
+    fn wanted(&self, kind: AlertKind) -> bool {
+        !self.sinks.is_empty() && self.settings.kinds.contains(&kind)
+    }
+
+    /// Delivers the alert to every sink in the background, if alerts of its kind are wanted.
+    pub fn fire(&self, kind: AlertKind, message: String) {
+        if !self.wanted(kind) {
+            return;
+        }
+
+        info!("[ALERTS] {kind:?}: {message}");
+        let alert = Alert {
+            time: Utc::now().to_rfc3339(),
+            agent: self.agent.to_owned(),
+            kind,
+            message,
+        };
+        let sinks = self.sinks.clone();
+        tokio::spawn(async move {
+            for sink in sinks.iter() {
+                if let Err(e) = sink.send(&alert).await {
+                    info!("[ALERTS] Failed to send alert: {e}");
+                }
+            }
+        });
+    }
+
+    /// Counts an error the API returned, alerting when they pile up.
+    pub fn api_error(&self, action: &str, error: &str) {
+        let mut errors = self.errors.lock().unwrap();
+        if !repeated(&mut errors, Instant::now(), self.settings.api_errors) {
+            return;
+        }
+
+        errors.clear();
+        let message = format!(
+            "{} API errors within {} minutes, the last one to {action}: {error}",
+            self.settings.api_errors,
+            ERROR_WINDOW.as_secs() / 60
+        );
+        self.fire(AlertKind::ApiErrors, message);
+    }
+
+    /// Alerts when our credits cross the threshold, either way.
+    pub fn credits(&self, credits: i64) {
+        let Some(threshold) = self.settings.credits else {
+            return;
+        };
+
+        let above = credits >= threshold;
+        let previous = self.above_credits.lock().unwrap().replace(above);
+        if previous.is_some_and(|p| p != above) {
+            let direction = if above { "above" } else { "below" };
+            let message = format!("Credits went {direction} {threshold}: {credits}");
+            self.fire(AlertKind::Credits, message);
+        }
+    }
+
+    /// Alerts once about each announcement of a server reset.
+    pub fn announcements(&self, status: &GetStatus200Response) {
+        let mut seen = self.announcements.lock().unwrap();
+        for announcement in &status.announcements {
+            let text = format!("{} {}", announcement.title, announcement.body);
+            if text.to_ascii_lowercase().contains("reset") && seen.insert(text) {
+                let message = format!(
+                    "Server reset announced: {}: {} (next reset {})",
+                    announcement.title, announcement.body, status.server_resets.next
+                );
+                self.fire(AlertKind::ResetAnnounced, message);
+            }
+        }
+    }
+}
+
+/// Records an error at `now`, forgetting the ones out of the window. Returns whether there are
+/// `threshold` errors in it.
+fn repeated(errors: &mut VecDeque<Instant>, now: Instant, threshold: usize) -> bool {
+    errors.push_back(now);
+    while errors.front().is_some_and(|e| now - *e > ERROR_WINDOW) {
+        errors.pop_front();
+    }
+    errors.len() >= threshold
+}
+
+/// Whether the contract is accepted but still not fulfilled this close to its deadline.
+fn nearing_deadline(contract: &Contract, now: DateTime<Utc>, within: chrono::Duration) -> bool {
+    contract.accepted && !contract.fulfilled && parse_time(&contract.terms.deadline) - now <= within
+}
+
+/// Periodically checks the fleet for ships left idle and contracts running late.
+pub struct AlertMonitor {
+    alerts: Alerts,
+    client: Client,
+    scheduler: SchedulerHandle,
+    idle_since: Mutex<HashMap<String, DateTime<Utc>>>,
+    warned_contracts: Mutex<HashSet<String>>,
+}
+
+impl AlertMonitor {
+    pub fn new(session: &Session, scheduler: SchedulerHandle) -> Self {
+        Self {
+            alerts: session.alerts.clone(),
+            client: Client::new("ALERTS".to_owned(), session),
+            scheduler,
+            idle_since: Mutex::default(),
+            warned_contracts: Mutex::default(),
+        }
+    }
+
+    pub async fn run(&self, mut shutdown: Shutdown) {
+        let mut checks = interval(Duration::from_secs(300));
+        loop {
+            tokio::select! {
+                _ = checks.tick() => {}
+                _ = shutdown.requested() => return,
+            }
+
+            self.check_idle_ships().await;
+            self.check_contracts().await;
+        }
+    }
+
+    /// Alerts once per idle spell about ships with nothing to do for too long. Paused ships
+    /// are left alone.
+    async fn check_idle_ships(&self) {
+        let now = Utc::now();
+        let statuses = self.scheduler.inspect().await;
+        let mut idle_since = self.idle_since.lock().unwrap();
+
+        for status in statuses {
+            if status.behaviour.is_some() || status.running || status.paused {
+                idle_since.remove(&status.ship_symbol);
+                continue;
+            }
+
+            let since = *idle_since.entry(status.ship_symbol.clone()).or_insert(now);
+            if now - since >= self.alerts.settings.idle {
+                let minutes = (now - since).num_minutes();
+                let message = format!("{} has been idle for {minutes} minutes", status.ship_symbol);
+                self.alerts.fire(AlertKind::ShipIdle, message);
+                // Alert again after another idle period
+                idle_since.insert(status.ship_symbol, now);
+            }
+        }
+    }
+
+    async fn check_contracts(&self) {
+        if !self.alerts.wanted(AlertKind::ContractDeadline) {
+            return;
+        }
+
+        let now = Utc::now();
+        let contracts = self.client.get_contracts().await;
+        let mut warned = self.warned_contracts.lock().unwrap();
+        for contract in contracts {
+            let within = self.alerts.settings.contract_deadline;
+            if nearing_deadline(&contract, now, within) && warned.insert(contract.id.clone()) {
+                let message = format!(
+                    "Contract {} is due {} and not fulfilled yet",
+                    contract.id, contract.terms.deadline
+                );
+                self.alerts.fire(AlertKind::ContractDeadline, message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::post, Json, Router};
+    use serde_json::Value;
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+
+    /// A local stand-in for a webhook, handing over every body POSTed to it.
+    async fn webhook_receiver() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |Json(body): Json<Value>| async move {
+                sender.send(body).unwrap();
+            }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, receiver)
+    }
+
+    #[tokio::test]
+    async fn webhooks_receive_alerts_as_json() {
+        let (url, mut received) = webhook_receiver().await;
+        let alert = Alert {
+            time: "2024-01-20T00:00:00+00:00".to_owned(),
+            agent: "MXZ".to_owned(),
+            kind: AlertKind::ShipIdle,
+            message: "MXZ-3 has been idle for 30 minutes".to_owned(),
+        };
+
+        WebhookSink::new(&url).send(&alert).await.unwrap();
+
+        let body = received.recv().await.unwrap();
+        assert_eq!(body["kind"], "ship_idle");
+        assert_eq!(body["message"], "MXZ-3 has been idle for 30 minutes");
+    }
+
+    #[test]
+    fn malformed_settings_are_left_out() {
+        let account = Account::in_directory("ALERTTEST", std::env::temp_dir());
+        std::env::set_var("ALERT_EVENTS_ALERTTEST", "ship_idle,nope");
+        std::env::set_var("ALERT_API_ERRORS_ALERTTEST", "many");
+        std::env::set_var("ALERT_CREDITS_ALERTTEST", "50000");
+        std::env::set_var("ALERT_SINKS_ALERTTEST", "stdout,pager:123");
+
+        let settings = AlertSettings::from_env(&account);
+        assert_eq!(settings.kinds, HashSet::from([AlertKind::ShipIdle]));
+        assert_eq!(settings.api_errors, 5);
+        assert_eq!(settings.credits, Some(50_000));
+        assert_eq!(Alerts::from_env(&account).sinks.len(), 1);
+    }
+
+    #[test]
+    fn only_errors_close_together_are_repeated() {
+        let start = Instant::now();
+        let mut errors = VecDeque::new();
+
+        assert!(!repeated(&mut errors, start, 3));
+        assert!(!repeated(&mut errors, start + Duration::from_secs(500), 3));
+        assert!(!repeated(&mut errors, start + Duration::from_secs(700), 3));
+        assert!(repeated(&mut errors, start + Duration::from_secs(710), 3));
+    }
+
+    #[test]
+    fn contracts_near_their_deadline_are_found() {
+        let json = r#"{"id":"clrk1","factionSymbol":"COSMIC","type":"PROCUREMENT","terms":{"deadline":"2024-01-27T12:00:00.000Z","payment":{"onAccepted":1000,"onFulfilled":5000}},"accepted":true,"fulfilled":false,"expiration":"2024-01-21T12:00:00.000Z"}"#;
+        let contract: Contract = serde_json::from_str(json).unwrap();
+        let six_hours = chrono::Duration::hours(6);
+
+        assert!(nearing_deadline(
+            &contract,
+            parse_time("2024-01-27T08:00:00Z"),
+            six_hours
+        ));
+        assert!(!nearing_deadline(
+            &contract,
+            parse_time("2024-01-26T08:00:00Z"),
+            six_hours
+        ));
+        assert_eq!(
+            AlertKind::from_name(" Contract_Deadline"),
+            Some(AlertKind::ContractDeadline)
+        );
+    }
+}
@@ -0,0 +1,942 @@
+use async_trait::async_trait;
+use log::info;
+use serde::{Deserialize, Serialize};
+use spacedust::models::{Ship, ShipNavStatus, TradeSymbol};
+
+use crate::{
+    client::ExtractResourceError,
+    construction::ConstructionProject,
+    contracts::{held_units, Delivery, Source},
+    manager::Manager,
+    markets::TradeRoute,
+    outfitting::Upgrade,
+    roles::Role,
+    scheduler::{Behaviour, Next, Reason},
+};
+
+/// The behaviour a ship works under for the given role.
+pub fn for_role(role: Role) -> Box<dyn Behaviour> {
+    match role {
+        Role::Miner => Box::new(Mining {
+            state: MiningState::Starting,
+        }),
+        Role::Surveyor => Box::new(Surveying {
+            state: SurveyingState::Starting,
+        }),
+        Role::Trader => Box::new(Trading {
+            state: TradingState::Planning,
+        }),
+        Role::Contractor => Box::new(Contracting {
+            state: ContractingState::Sourcing,
+        }),
+        Role::Hauler => Box::new(Hauling {
+            state: HaulingState::Starting,
+        }),
+        Role::Probe => Box::new(Exploring {
+            state: ExploringState::Choosing,
+        }),
+        Role::Idle => Box::new(Idle),
+    }
+}
+
+/// A behaviour's state as persisted between runs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "behaviour", content = "state")]
+pub enum SavedBehaviour {
+    Mining(MiningState),
+    Surveying(SurveyingState),
+    Trading(TradingState),
+    Contracting(ContractingState),
+    Hauling(HaulingState),
+    Exploring(ExploringState),
+    /// Saved by earlier runs, which only kept the ship's role.
+    Role(Role),
+    Idle,
+    Construction(ConstructionProject),
+}
+
+impl SavedBehaviour {
+    /// The role the behaviour works for, if it works for one.
+    pub fn role(&self) -> Option<Role> {
+        match self {
+            SavedBehaviour::Mining(_) => Some(Role::Miner),
+            SavedBehaviour::Surveying(_) => Some(Role::Surveyor),
+            SavedBehaviour::Trading(_) => Some(Role::Trader),
+            SavedBehaviour::Contracting(_) => Some(Role::Contractor),
+            SavedBehaviour::Hauling(_) => Some(Role::Hauler),
+            SavedBehaviour::Exploring(_) => Some(Role::Probe),
+            SavedBehaviour::Role(role) => Some(*role),
+            SavedBehaviour::Idle => Some(Role::Idle),
+            SavedBehaviour::Construction(_) => None,
+        }
+    }
+
+    pub fn restore(self) -> Box<dyn Behaviour> {
+        match self {
+            SavedBehaviour::Mining(state) => Box::new(Mining { state }),
+            SavedBehaviour::Surveying(state) => Box::new(Surveying { state }),
+            SavedBehaviour::Trading(state) => Box::new(Trading { state }),
+            SavedBehaviour::Contracting(state) => Box::new(Contracting { state }),
+            SavedBehaviour::Hauling(state) => Box::new(Hauling { state }),
+            SavedBehaviour::Exploring(state) => Box::new(Exploring { state }),
+            SavedBehaviour::Role(role) => for_role(role),
+            SavedBehaviour::Idle => Box::new(Idle),
+            SavedBehaviour::Construction(project) => Box::new(project),
+        }
+    }
+}
+
+/// Where the ship is headed if it's travelling.
+fn destination(ship: &Ship) -> Option<&str> {
+    (ship.nav.status == ShipNavStatus::InTransit)
+        .then_some(ship.nav.route.destination.symbol.as_str())
+}
+
+/// Waits this long before trying again when the system has no asteroid field.
+const NO_FIELD_RETRY_SECONDS: u64 = 300;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum MiningState {
+    Starting,
+    Navigating { to: String },
+    Unloading { field: String },
+    Extracting { field: String },
+    BuyingMount { field: String, upgrade: Upgrade },
+    InstallingMount { field: String, upgrade: Upgrade },
+}
+
+/// Mines the system's asteroid field, offloading to haulers or selling in between.
+pub struct Mining {
+    state: MiningState,
+}
+
+#[async_trait]
+impl Behaviour for Mining {
+    fn name(&self) -> String {
+        "Mining".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        format!("Mining ({:?})", self.state)
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Mining(self.state.clone()))
+    }
+
+    fn reconcile(&mut self, ship: &Ship) {
+        let field = match &self.state {
+            // Trips to the market or shipyard depart again from wherever the ship is.
+            MiningState::Starting
+            | MiningState::BuyingMount { .. }
+            | MiningState::InstallingMount { .. } => return,
+            MiningState::Navigating { to } => to,
+            MiningState::Unloading { field } | MiningState::Extracting { field } => field,
+        }
+        .to_owned();
+
+        if destination(ship).is_some() || ship.nav.waypoint_symbol != field {
+            self.state = MiningState::Navigating { to: field };
+        } else if ship.cargo.capacity - ship.cargo.units < 3 {
+            self.state = MiningState::Unloading { field };
+        }
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        let context = ship_symbol;
+
+        match &self.state {
+            MiningState::Starting => {
+                let Some(field) = manager.asteroid_field(ship_symbol).await else {
+                    return Next::after(NO_FIELD_RETRY_SECONDS, Reason::Ready);
+                };
+
+                self.state = MiningState::Navigating { to: field };
+                Next::now()
+            }
+            MiningState::Navigating { to } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, to).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                self.state = MiningState::Unloading {
+                    field: to.to_owned(),
+                };
+                Next::now()
+            }
+            MiningState::Unloading { field } => {
+                manager.unload(ship_symbol).await;
+
+                self.state = match manager.outfitter().plan_upgrade(ship_symbol).await {
+                    Some(upgrade) => MiningState::BuyingMount {
+                        field: field.to_owned(),
+                        upgrade,
+                    },
+                    None => MiningState::Extracting {
+                        field: field.to_owned(),
+                    },
+                };
+                Next::now()
+            }
+            MiningState::BuyingMount { field, upgrade } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, &upgrade.market).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                self.state = if manager.outfitter().buy_mount(ship_symbol, upgrade).await {
+                    MiningState::InstallingMount {
+                        field: field.to_owned(),
+                        upgrade: upgrade.clone(),
+                    }
+                } else {
+                    MiningState::Navigating {
+                        to: field.to_owned(),
+                    }
+                };
+                Next::now()
+            }
+            MiningState::InstallingMount { field, upgrade } => {
+                if let Some(arrival) = manager
+                    .client()
+                    .depart(ship_symbol, &upgrade.shipyard)
+                    .await
+                {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager
+                    .outfitter()
+                    .install_mount(ship_symbol, upgrade)
+                    .await;
+                self.state = MiningState::Navigating {
+                    to: field.to_owned(),
+                };
+                Next::now()
+            }
+            MiningState::Extracting { field } => {
+                let survey = manager.surveys().best(field);
+
+                match manager
+                    .client()
+                    .extract_once(ship_symbol, survey.clone())
+                    .await
+                {
+                    Result::Ok(r) => {
+                        let cargo = r.cargo;
+                        let yld = r.extraction.r#yield;
+                        let sleep_seconds = r.cooldown.remaining_seconds as u64;
+
+                        info!("[{context}] extraction cooldown, yield={}x{}, inventory={}/{}, sleeping for {sleep_seconds} seconds", yld.units, yld.symbol.to_string(), cargo.units, cargo.capacity);
+
+                        if cargo.capacity - cargo.units < 3 {
+                            self.state = MiningState::Unloading {
+                                field: field.to_owned(),
+                            };
+                        }
+                        Next::after(sleep_seconds, Reason::Cooldown)
+                    }
+                    Result::Err(e) => match e.error.data {
+                        ExtractResourceError::Cooldown { cooldown } => {
+                            Next::after(cooldown.remaining_seconds, Reason::Cooldown)
+                        }
+                        ExtractResourceError::Cargo { .. } => {
+                            self.state = MiningState::Unloading {
+                                field: field.to_owned(),
+                            };
+                            Next::now()
+                        }
+                        ExtractResourceError::Other(_) => match survey {
+                            Some(s) => {
+                                info!("[{context}] survey {} unusable: {e}", s.signature);
+                                manager.surveys().remove(&s.signature);
+                                Next::now()
+                            }
+                            None => {
+                                info!("[{context}] extraction failed: {e}");
+                                self.state = MiningState::Starting;
+                                Next::after(60, Reason::Ready)
+                            }
+                        },
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum SurveyingState {
+    Starting,
+    Navigating { to: String },
+    Surveying { field: String },
+}
+
+/// Surveys the system's asteroid field, sharing the results with the miners.
+pub struct Surveying {
+    state: SurveyingState,
+}
+
+#[async_trait]
+impl Behaviour for Surveying {
+    fn name(&self) -> String {
+        "Surveying".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        format!("Surveying ({:?})", self.state)
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Surveying(self.state.clone()))
+    }
+
+    fn reconcile(&mut self, ship: &Ship) {
+        if let SurveyingState::Surveying { field } = &self.state {
+            if destination(ship).is_some() || &ship.nav.waypoint_symbol != field {
+                self.state = SurveyingState::Navigating {
+                    to: field.to_owned(),
+                };
+            }
+        }
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        let context = ship_symbol;
+
+        match &self.state {
+            SurveyingState::Starting => {
+                let Some(field) = manager.asteroid_field(ship_symbol).await else {
+                    return Next::after(NO_FIELD_RETRY_SECONDS, Reason::Ready);
+                };
+
+                self.state = SurveyingState::Navigating { to: field };
+                Next::now()
+            }
+            SurveyingState::Navigating { to } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, to).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                let ship = manager.client().get_ship(ship_symbol).await;
+                if ship.nav.status == ShipNavStatus::Docked {
+                    manager.client().orbit_ship(ship_symbol).await;
+                }
+
+                self.state = SurveyingState::Surveying {
+                    field: to.to_owned(),
+                };
+                Next::now()
+            }
+            SurveyingState::Surveying { .. } => {
+                match manager.client().create_survey(ship_symbol).await {
+                    Result::Ok(r) => {
+                        info!("[{context}] Created {} surveys", r.surveys.len());
+                        manager.surveys().add(r.surveys);
+                        Next::after(r.cooldown.remaining_seconds as u64, Reason::Cooldown)
+                    }
+                    Result::Err(e) => {
+                        info!("[{context}] Failed to survey: {e}");
+                        self.state = SurveyingState::Starting;
+                        Next::after(60, Reason::Ready)
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum TradingState {
+    Planning,
+    Buying { plan: TradeRoute },
+    Selling { plan: TradeRoute },
+    Exploring { market: String },
+}
+
+impl TradingState {
+    fn describe(&self) -> String {
+        match self {
+            TradingState::Planning => "Planning".to_owned(),
+            TradingState::Buying { plan } => format!(
+                "Buying {}x{} at {}",
+                plan.units,
+                plan.trade_symbol.to_string(),
+                plan.buy_at
+            ),
+            TradingState::Selling { plan } => format!(
+                "Selling {}x{} at {}",
+                plan.units,
+                plan.trade_symbol.to_string(),
+                plan.sell_at
+            ),
+            TradingState::Exploring { market } => format!("Exploring {market}"),
+        }
+    }
+
+    fn reconcile(&mut self, ship: &Ship) {
+        *self = match self {
+            TradingState::Planning | TradingState::Exploring { .. } => return,
+            TradingState::Buying { plan } => {
+                // Bought before the restart but didn't get to record it.
+                let held = held_units(&ship.cargo.inventory, plan.trade_symbol);
+                if held == 0 {
+                    return;
+                }
+                TradingState::Selling {
+                    plan: TradeRoute {
+                        units: held,
+                        ..plan.clone()
+                    },
+                }
+            }
+            TradingState::Selling { plan } => {
+                let held = held_units(&ship.cargo.inventory, plan.trade_symbol);
+                if held == 0 {
+                    TradingState::Planning
+                } else {
+                    TradingState::Selling {
+                        plan: TradeRoute {
+                            units: held,
+                            ..plan.clone()
+                        },
+                    }
+                }
+            }
+        };
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        match self {
+            TradingState::Planning => {
+                if let Some(plan) = manager.plan_trade(ship_symbol).await {
+                    *self = TradingState::Buying { plan };
+                    return Next::now();
+                }
+
+                match manager.market_to_explore(ship_symbol).await {
+                    Some(market) => {
+                        *self = TradingState::Exploring { market };
+                        Next::now()
+                    }
+                    None => Next::after(NO_MARKET_RETRY_SECONDS, Reason::Ready),
+                }
+            }
+            TradingState::Buying { plan } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, &plan.buy_at).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                let units = manager.buy_route(ship_symbol, plan).await;
+                *self = if units == 0 {
+                    TradingState::Planning
+                } else {
+                    TradingState::Selling {
+                        plan: TradeRoute {
+                            units,
+                            ..plan.clone()
+                        },
+                    }
+                };
+                Next::now()
+            }
+            TradingState::Selling { plan } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, &plan.sell_at).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager.sell_route(ship_symbol, plan).await;
+                *self = TradingState::Planning;
+                Next::now()
+            }
+            TradingState::Exploring { market } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, market).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager.visit_market(ship_symbol).await;
+                *self = TradingState::Planning;
+                Next::now()
+            }
+        }
+    }
+}
+
+/// Waits this long before looking again when the system has no market to explore.
+const NO_MARKET_RETRY_SECONDS: u64 = 60;
+
+/// Runs the most profitable known trade routes, exploring markets while there is none.
+pub struct Trading {
+    state: TradingState,
+}
+
+#[async_trait]
+impl Behaviour for Trading {
+    fn name(&self) -> String {
+        "Trading".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        format!("Trading ({})", self.state.describe())
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Trading(self.state.clone()))
+    }
+
+    fn reconcile(&mut self, ship: &Ship) {
+        self.state.reconcile(ship);
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        self.state.step(manager, ship_symbol).await
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum ContractingState {
+    Sourcing,
+    Buying {
+        market: String,
+        contract: Delivery,
+    },
+    Delivering {
+        contract: Delivery,
+    },
+    /// Trading or exploring until the next contract, for one trade run.
+    Trading {
+        state: TradingState,
+    },
+}
+
+/// Works on procurement contracts, trading while there is none.
+pub struct Contracting {
+    state: ContractingState,
+}
+
+#[async_trait]
+impl Behaviour for Contracting {
+    fn name(&self) -> String {
+        "Contracting".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        match &self.state {
+            ContractingState::Sourcing => "Contracting (Sourcing)".to_owned(),
+            ContractingState::Buying { market, contract } => format!(
+                "Contracting (Buying {}x{} at {market} for {})",
+                contract.units,
+                contract.trade_symbol.to_string(),
+                contract.contract_id
+            ),
+            ContractingState::Delivering { contract } => format!(
+                "Contracting (Delivering {}x{} to {} for {})",
+                contract.units,
+                contract.trade_symbol.to_string(),
+                contract.destination,
+                contract.contract_id
+            ),
+            ContractingState::Trading { state } => {
+                format!("Contracting (Trading, {})", state.describe())
+            }
+        }
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Contracting(self.state.clone()))
+    }
+
+    fn reconcile(&mut self, ship: &Ship) {
+        match &mut self.state {
+            ContractingState::Sourcing => {}
+            ContractingState::Buying { contract, .. } => {
+                // Bought before the restart but didn't get to record it.
+                let held = held_units(&ship.cargo.inventory, contract.trade_symbol);
+                if held > 0 {
+                    self.state = ContractingState::Delivering {
+                        contract: Delivery {
+                            units: held.min(contract.units),
+                            ..contract.clone()
+                        },
+                    };
+                }
+            }
+            ContractingState::Delivering { contract } => {
+                if held_units(&ship.cargo.inventory, contract.trade_symbol) == 0 {
+                    self.state = ContractingState::Sourcing;
+                }
+            }
+            ContractingState::Trading { state } => state.reconcile(ship),
+        }
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        match &mut self.state {
+            ContractingState::Sourcing => {
+                let Some(active) = manager.contractor().active_contract().await else {
+                    manager.negotiate_at_headquarters(ship_symbol).await;
+                    self.state = ContractingState::Trading {
+                        state: TradingState::Planning,
+                    };
+                    return Next::now();
+                };
+
+                self.state = match manager.contractor().source(ship_symbol, &active).await {
+                    Some(Source::Held(contract)) => ContractingState::Delivering { contract },
+                    Some(Source::Market {
+                        market,
+                        delivery: contract,
+                    }) => ContractingState::Buying { market, contract },
+                    // Nobody we know of sells the goods, look for them.
+                    None => match manager.market_to_explore(ship_symbol).await {
+                        Some(market) => ContractingState::Trading {
+                            state: TradingState::Exploring { market },
+                        },
+                        None => return Next::after(NO_MARKET_RETRY_SECONDS, Reason::Ready),
+                    },
+                };
+                Next::now()
+            }
+            ContractingState::Buying { market, contract } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, market).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                self.state = match manager.contractor().buy(ship_symbol, contract).await {
+                    Some(contract) => ContractingState::Delivering { contract },
+                    None => ContractingState::Sourcing,
+                };
+                Next::now()
+            }
+            ContractingState::Delivering { contract } => {
+                if let Some(arrival) = manager
+                    .client()
+                    .depart(ship_symbol, &contract.destination)
+                    .await
+                {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager.contractor().deliver(ship_symbol, contract).await;
+                self.state = ContractingState::Sourcing;
+                Next::now()
+            }
+            ContractingState::Trading { state } => {
+                let next = state.step(manager, ship_symbol).await;
+                // Back to planning once the trade run or the visit is over.
+                if *state == TradingState::Planning {
+                    self.state = ContractingState::Sourcing;
+                }
+                next
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum HaulingState {
+    Starting,
+    Waiting { field: String },
+    Selling { field: String, market: String },
+}
+
+/// Waits at the asteroid field while the miners fill the hold, then sells the cargo at the
+/// market paying the most for it.
+pub struct Hauling {
+    state: HaulingState,
+}
+
+#[async_trait]
+impl Behaviour for Hauling {
+    fn name(&self) -> String {
+        "Hauling".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        format!("Hauling ({:?})", self.state)
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Hauling(self.state.clone()))
+    }
+
+    fn reconcile(&mut self, ship: &Ship) {
+        if let HaulingState::Selling { field, .. } = &self.state {
+            if ship.cargo.units == 0 {
+                self.state = HaulingState::Waiting {
+                    field: field.to_owned(),
+                };
+            }
+        }
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        match &self.state {
+            HaulingState::Starting => {
+                let Some(field) = manager.asteroid_field(ship_symbol).await else {
+                    return Next::after(NO_FIELD_RETRY_SECONDS, Reason::Ready);
+                };
+
+                self.state = HaulingState::Waiting { field };
+                Next::now()
+            }
+            HaulingState::Waiting { field } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, field).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                let Some(market) = manager.haul_destination(ship_symbol, field).await else {
+                    return Next::after(60, Reason::Ready);
+                };
+                self.state = HaulingState::Selling {
+                    field: field.to_owned(),
+                    market,
+                };
+                Next::now()
+            }
+            HaulingState::Selling { field, market } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, market).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager.sell_haul(ship_symbol).await;
+                self.state = HaulingState::Waiting {
+                    field: field.to_owned(),
+                };
+                Next::now()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum ExploringState {
+    Choosing,
+    Visiting { market: String },
+}
+
+/// Visits the markets we know the least about, one after the other.
+pub struct Exploring {
+    state: ExploringState,
+}
+
+#[async_trait]
+impl Behaviour for Exploring {
+    fn name(&self) -> String {
+        "Exploring".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        format!("Exploring ({:?})", self.state)
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Exploring(self.state.clone()))
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        match &self.state {
+            ExploringState::Choosing => {
+                let Some(market) = manager.market_to_explore(ship_symbol).await else {
+                    return Next::after(NO_MARKET_RETRY_SECONDS, Reason::Ready);
+                };
+
+                self.state = ExploringState::Visiting { market };
+                Next::now()
+            }
+            ExploringState::Visiting { market } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, market).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager.visit_market(ship_symbol).await;
+                self.state = ExploringState::Choosing;
+                Next::after(0, Reason::MarketRefresh)
+            }
+        }
+    }
+}
+
+/// Nothing we automate suits the ship: it just waits to be given another role.
+pub struct Idle;
+
+#[async_trait]
+impl Behaviour for Idle {
+    fn name(&self) -> String {
+        "Idle".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        "Idle".to_owned()
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Idle)
+    }
+
+    async fn step(&mut self, _manager: &Manager, _ship_symbol: &str) -> Next {
+        Next::after(300, Reason::Ready)
+    }
+}
+
+/// A one-off order given by hand. The ship goes back to its role once it is carried out.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "order", rename_all = "camelCase")]
+pub enum Order {
+    #[serde(rename_all = "camelCase")]
+    Navigate { waypoint_symbol: String },
+    /// Sells the units of the good, or the hold down to the sell floor if no good is given.
+    #[serde(rename_all = "camelCase")]
+    Sell {
+        trade_symbol: Option<TradeSymbol>,
+        units: Option<i32>,
+    },
+}
+
+#[async_trait]
+impl Behaviour for Order {
+    fn name(&self) -> String {
+        "Order".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Order::Navigate { waypoint_symbol } => format!("Navigating to {waypoint_symbol}"),
+            Order::Sell { .. } => "Selling".to_owned(),
+        }
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        None
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        match self {
+            Order::Navigate { waypoint_symbol } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, waypoint_symbol).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+            }
+            Order::Sell {
+                trade_symbol,
+                units,
+            } => {
+                manager.client().dock_ship(ship_symbol).await;
+                match (trade_symbol, units) {
+                    (Some(trade_symbol), Some(units)) => {
+                        manager
+                            .client()
+                            .sell(ship_symbol, *trade_symbol, *units)
+                            .await;
+                    }
+                    _ => manager.sell_cargo(ship_symbol).await,
+                }
+            }
+        }
+
+        info!("[{ship_symbol}] {} done", self.describe());
+        Next::Done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_behaviours_round_trip() {
+        let saved = SavedBehaviour::Trading(TradingState::Selling {
+            plan: TradeRoute {
+                trade_symbol: TradeSymbol::Iron,
+                buy_at: "X1-ZA40-A1".into(),
+                sell_at: "X1-ZA40-B2".into(),
+                buy_price: 10,
+                sell_price: 25,
+                units: 20,
+            },
+        });
+
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(
+            json,
+            "{\"behaviour\":\"Trading\",\"state\":{\"step\":\"selling\",\"plan\":{\"tradeSymbol\":\"IRON\",\"buyAt\":\"X1-ZA40-A1\",\"sellAt\":\"X1-ZA40-B2\",\"buyPrice\":10,\"sellPrice\":25,\"units\":20}}}"
+        );
+        assert_eq!(
+            serde_json::from_str::<SavedBehaviour>(&json).unwrap(),
+            saved
+        );
+
+        let saved = SavedBehaviour::Contracting(ContractingState::Trading {
+            state: TradingState::Exploring {
+                market: "X1-ZA40-B2".into(),
+            },
+        });
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(
+            json,
+            "{\"behaviour\":\"Contracting\",\"state\":{\"step\":\"trading\",\"state\":{\"step\":\"exploring\",\"market\":\"X1-ZA40-B2\"}}}"
+        );
+        assert_eq!(
+            serde_json::from_str::<SavedBehaviour>(&json).unwrap(),
+            saved
+        );
+
+        let saved = SavedBehaviour::Role(Role::Hauler);
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SavedBehaviour>(&json).unwrap(),
+            saved
+        );
+
+        let json = serde_json::to_string(&SavedBehaviour::Idle).unwrap();
+        assert_eq!(json, "{\"behaviour\":\"Idle\"}");
+        assert_eq!(
+            serde_json::from_str::<SavedBehaviour>(&json).unwrap(),
+            SavedBehaviour::Idle
+        );
+    }
+}
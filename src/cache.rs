@@ -0,0 +1,108 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use log::info;
+
+lazy_static! {
+    pub static ref CACHE: Cache = Cache::default();
+}
+
+/// How long a cached response stays valid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ttl {
+    /// Doesn't change until the server resets, like systems and their waypoints.
+    Static,
+    Expires(Duration),
+}
+
+struct Entry {
+    stored: Instant,
+    value: Box<dyn Any + Send>,
+}
+
+/// Responses of the API calls returning data that rarely changes, so repeated calls don't eat
+/// into the rate limit. Everything is dropped when the server resets.
+#[derive(Clone, Default)]
+pub struct Cache {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    reset_date: Arc<Mutex<Option<String>>>,
+}
+
+impl Cache {
+    /// The value cached under `key`, if it is younger than the TTL.
+    pub fn get<T: Clone + 'static>(&self, key: &str, ttl: Ttl) -> Option<T> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+
+        if let Ttl::Expires(ttl) = ttl {
+            if entry.stored.elapsed() >= ttl {
+                return None;
+            }
+        }
+
+        entry.value.downcast_ref::<T>().cloned()
+    }
+
+    pub fn insert<T: Clone + Send + 'static>(&self, key: &str, value: &T) {
+        self.entries.lock().unwrap().insert(
+            key.to_owned(),
+            Entry {
+                stored: Instant::now(),
+                value: Box::new(value.clone()),
+            },
+        );
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    /// Clears everything when the server's reset date differs from the one seen last. Returns
+    /// whether it did.
+    pub fn observe_reset(&self, reset_date: &str) -> bool {
+        let mut last = self.reset_date.lock().unwrap();
+        let reset = last.as_deref().is_some_and(|l| l != reset_date);
+        if reset {
+            info!("[CACHE] Server reset on {reset_date}, clearing cached responses");
+            self.entries.lock().unwrap().clear();
+        }
+
+        *last = Some(reset_date.to_owned());
+        reset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_expire_and_clear_on_reset() {
+        let cache = Cache::default();
+        cache.insert("waypoints/X1-A", &vec!["X1-A-1".to_owned()]);
+        cache.insert("market/X1-A-1", &42);
+
+        assert_eq!(
+            cache.get::<Vec<String>>("waypoints/X1-A", Ttl::Static),
+            Some(vec!["X1-A-1".to_owned()])
+        );
+        assert_eq!(
+            cache.get::<i32>("market/X1-A-1", Ttl::Expires(Duration::ZERO)),
+            None
+        );
+        assert_eq!(cache.get::<String>("waypoints/X1-A", Ttl::Static), None);
+
+        assert!(!cache.observe_reset("2024-01-07"));
+        assert!(!cache.observe_reset("2024-01-07"));
+        assert!(cache.observe_reset("2024-01-21"));
+        assert_eq!(
+            cache.get::<Vec<String>>("waypoints/X1-A", Ttl::Static),
+            None
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use log::info;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Error, Middleware, Next, Result};
+use serde::{Deserialize, Serialize};
+use task_local_extensions::Extensions;
+
+use crate::configuration::optional_var;
+
+/// One request we sent and the response we got. Headers are left out so tokens aren't written
+/// to the cassette.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interaction {
+    pub agent: String,
+    pub method: String,
+    pub url: String,
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub response_body: String,
+}
+
+/// Identifies the request an interaction answers.
+type Key = (String, String, Option<String>);
+
+impl Interaction {
+    fn key(&self) -> Key {
+        (
+            self.method.to_owned(),
+            self.url.to_owned(),
+            self.request_body.clone(),
+        )
+    }
+}
+
+fn key_of(request: &Request) -> Key {
+    let body = (request.body())
+        .and_then(|b| b.as_bytes())
+        .map(|b| String::from_utf8_lossy(b).into_owned());
+    (
+        request.method().to_string(),
+        request.url().to_string(),
+        body,
+    )
+}
+
+/// The responses not replayed yet, for each request in the order they were recorded.
+type Responses = Arc<Mutex<HashMap<Key, VecDeque<Interaction>>>>;
+
+lazy_static! {
+    /// The responses left in each agent's cassette, shared by all the agent's configurations so
+    /// none is replayed twice.
+    static ref REPLAYS: Mutex<HashMap<(String, PathBuf), Responses>> = Mutex::default();
+}
+
+enum Mode {
+    Record(Mutex<File>),
+    Replay(Responses),
+}
+
+/// Records every request an agent sends and the response to a JSON lines cassette, or answers
+/// requests from one instead of the API so a run can be reproduced offline.
+pub struct CassetteMiddleware {
+    agent: String,
+    path: PathBuf,
+    mode: Mode,
+}
+
+impl CassetteMiddleware {
+    pub fn record(agent: &str, path: &Path) -> Self {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+
+        Self {
+            agent: agent.to_owned(),
+            path: path.to_owned(),
+            mode: Mode::Record(Mutex::new(file)),
+        }
+    }
+
+    /// Replays the agent's interactions recorded in the cassette. Identical requests get their
+    /// responses in the order they were recorded.
+    pub fn replay(agent: &str, path: &Path) -> Self {
+        let responses = REPLAYS
+            .lock()
+            .unwrap()
+            .entry((agent.to_owned(), path.to_owned()))
+            .or_insert_with(|| Arc::new(Mutex::new(load(agent, path))))
+            .clone();
+
+        Self {
+            agent: agent.to_owned(),
+            path: path.to_owned(),
+            mode: Mode::Replay(responses),
+        }
+    }
+
+    /// The middleware `CASSETTE_RECORD` or `CASSETTE_REPLAY` asks for, with the cassette's path.
+    pub fn from_env(agent: &str) -> Option<Self> {
+        if let Some(path) = optional_var("CASSETTE_REPLAY") {
+            info!("[CASSETTE] {agent} replaying responses from {path}");
+            return Some(Self::replay(agent, Path::new(&path)));
+        }
+
+        let path = optional_var("CASSETTE_RECORD")?;
+        info!("[CASSETTE] {agent} recording requests to {path}");
+        Some(Self::record(agent, Path::new(&path)))
+    }
+
+    pub fn replaying(&self) -> bool {
+        matches!(self.mode, Mode::Replay(_))
+    }
+}
+
+/// The agent's interactions recorded in the cassette, by request.
+fn load(agent: &str, path: &Path) -> HashMap<Key, VecDeque<Interaction>> {
+    let file =
+        File::open(path).unwrap_or_else(|e| panic!("Can't open cassette {}: {e}", path.display()));
+
+    let mut responses: HashMap<_, VecDeque<_>> = HashMap::new();
+    for line in BufReader::new(file).lines() {
+        let interaction: Interaction = serde_json::from_str(&line.unwrap()).unwrap();
+        if interaction.agent == agent {
+            responses
+                .entry(interaction.key())
+                .or_default()
+                .push_back(interaction);
+        }
+    }
+    responses
+}
+
+#[async_trait]
+impl Middleware for CassetteMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let (method, url, request_body) = key_of(&req);
+
+        let file = match &self.mode {
+            Mode::Record(file) => file,
+            Mode::Replay(responses) => {
+                let key = (method, url, request_body);
+                let interaction = (responses.lock().unwrap().get_mut(&key))
+                    .and_then(|r| r.pop_front())
+                    .ok_or_else(|| {
+                        Error::middleware(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("No response left in the cassette for {} {}", key.0, key.1),
+                        ))
+                    })?;
+
+                let response = http::Response::builder()
+                    .status(interaction.status)
+                    .header("content-type", "application/json")
+                    .body(interaction.response_body)
+                    .unwrap();
+                return Ok(Response::from(response));
+            }
+        };
+
+        let response = next.run(req, extensions).await?;
+
+        // The body has to be read to record it, so the response is rebuilt around it
+        let status = response.status();
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in response.headers() {
+            builder = builder.header(name, value);
+        }
+        let body = response.bytes().await?;
+
+        let interaction = Interaction {
+            agent: self.agent.to_owned(),
+            method,
+            url,
+            request_body,
+            status: status.as_u16(),
+            response_body: String::from_utf8_lossy(&body).into_owned(),
+        };
+        let mut line = serde_json::to_string(&interaction).unwrap();
+        line.push('\n');
+        if let Err(e) = file.lock().unwrap().write_all(line.as_bytes()) {
+            info!("[CASSETTE] Failed to write to {}: {e}", self.path.display());
+        }
+
+        Ok(Response::from(builder.body(body).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use axum::{routing::post, Router};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn client(middleware: CassetteMiddleware) -> reqwest_middleware::ClientWithMiddleware {
+        reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(middleware)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn recorded_responses_are_replayed_offline() {
+        let app = Router::new().route(
+            "/my/ships/MXZ-1/dock",
+            post(|body: String| async move {
+                format!("{{\"data\":{{\"docked\":{}}}}}", body.is_empty())
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "http://{}/my/ships/MXZ-1/dock",
+            listener.local_addr().unwrap()
+        );
+        let server = tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let path = std::env::temp_dir().join(format!("cassette-{}.jsonl", std::process::id()));
+        let recorder = client(CassetteMiddleware::record("MXZ", &path));
+        let recorded = recorder
+            .post(&url)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(recorded, "{\"data\":{\"docked\":true}}");
+        server.abort();
+
+        let player = client(CassetteMiddleware::replay("MXZ", &path));
+        let replayed = player.post(&url).send().await.unwrap();
+        assert_eq!(replayed.status(), 200);
+        assert_eq!(replayed.text().await.unwrap(), recorded);
+        assert!(player.post(&url).send().await.is_err());
+        // Other configurations of the agent share what is left in the cassette
+        let other = client(CassetteMiddleware::replay("MXZ", &path));
+        assert!(other.post(&url).send().await.is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
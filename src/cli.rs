@@ -0,0 +1,290 @@
+use clap::{Parser, Subcommand};
+use serde::de::DeserializeOwned;
+use spacedust::models::{FactionSymbol, ShipType, TradeSymbol};
+
+use crate::{
+    accounts::Account,
+    client::Client,
+    manager::ManagerFactory,
+    setup::{command_ship, parse_faction, Setup},
+};
+
+/// Plays SpaceTraders, or operates its agents by hand.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// The agent to operate, the first one configured by default.
+    #[arg(long, global = true)]
+    pub agent: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Puts every agent's fleet to work, or only the one given with `--agent` (the default).
+    Run,
+    /// Registers the agent and sets it up to play.
+    Register {
+        agent_symbol: String,
+        /// The faction to join, one that is recruiting by default.
+        #[arg(long, value_parser = faction)]
+        faction: Option<FactionSymbol>,
+    },
+    /// The server's status and the agent's credits.
+    Status,
+    /// Lists the agent's ships.
+    Ships,
+    /// Orders a ship around.
+    Ship {
+        /// Like `MXZ-1`.
+        ship_symbol: String,
+        #[command(subcommand)]
+        action: ShipAction,
+    },
+    /// The goods traded at a market and their prices.
+    Market { waypoint_symbol: String },
+    /// Lists the agent's contracts.
+    Contracts,
+    /// Buys a ship at the cheapest shipyard of the system.
+    BuyShip {
+        #[arg(value_parser = from_name::<ShipType>)]
+        ship_type: ShipType,
+        /// The command ship's system by default.
+        #[arg(long)]
+        system: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ShipAction {
+    /// Sets off to the waypoint, without waiting for the ship to get there.
+    Navigate { waypoint_symbol: String },
+    /// Docks at the waypoint the ship is at.
+    Dock,
+    /// Leaves the dock for orbit.
+    Orbit,
+    /// Sells the units of the good, or the whole hold if no good is given.
+    Sell {
+        #[arg(value_parser = from_name::<TradeSymbol>, requires = "units")]
+        trade_symbol: Option<TradeSymbol>,
+        units: Option<i32>,
+    },
+    /// Fills the tank at the market the ship is docked at.
+    Refuel,
+}
+
+/// Parses an API enum from its name, like `SHIP_MINING_DRONE`, in any case.
+fn from_name<T: DeserializeOwned>(name: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(name.to_ascii_uppercase()))
+        .map_err(|_| format!("Unknown name {name}"))
+}
+
+fn faction(name: &str) -> Result<FactionSymbol, String> {
+    parse_faction(name).ok_or(format!("Unknown faction {name}"))
+}
+
+/// The system a waypoint symbol like `X1-ZA40-B7` is in.
+fn system_of(waypoint_symbol: &str) -> &str {
+    waypoint_symbol
+        .rsplit_once('-')
+        .map_or(waypoint_symbol, |(system, _)| system)
+}
+
+impl Cli {
+    /// The account chosen with `--agent`, or the first one configured.
+    pub fn account(&self) -> Account {
+        let mut accounts = Account::all_from_env();
+        match &self.agent {
+            None => accounts.remove(0),
+            Some(name) => accounts
+                .into_iter()
+                .find(|a| a.name.eq_ignore_ascii_case(name))
+                .unwrap_or_else(|| panic!("No agent {name} configured")),
+        }
+    }
+}
+
+/// Runs one of the commands operating an agent by hand.
+pub async fn execute(command: Command, account: &Account) {
+    if let Command::Register {
+        agent_symbol,
+        faction,
+    } = command
+    {
+        let credentials = Setup::setup_account(account, &agent_symbol, faction).await;
+        println!(
+            "Registered {} with {} at {}",
+            credentials.agent_symbol, credentials.faction, credentials.headquarters
+        );
+        return;
+    }
+
+    let session = account.connect();
+    let client = Client::new("CLI".to_owned(), &session);
+
+    match command {
+        Command::Run | Command::Register { .. } => unreachable!(),
+        Command::Status => {
+            let status = client.get_status().await;
+            let agent = client.get_my_agent().await;
+            println!(
+                "Server: {} (reset {}, next reset {})",
+                status.status, status.reset_date, status.server_resets.next
+            );
+            println!(
+                "{}: {} credits, {} ships, headquarters {}",
+                agent.symbol, agent.credits, agent.ship_count, agent.headquarters
+            );
+        }
+        Command::Ships => {
+            for ship in client.get_my_ships().await {
+                println!(
+                    "{} {:?} {:?} at {}, fuel {}/{}, cargo {}/{}",
+                    ship.symbol,
+                    ship.registration.role,
+                    ship.nav.status,
+                    ship.nav.waypoint_symbol,
+                    ship.fuel.current,
+                    ship.fuel.capacity,
+                    ship.cargo.units,
+                    ship.cargo.capacity
+                );
+            }
+        }
+        Command::Ship {
+            ship_symbol,
+            action,
+        } => operate_ship(&client, &ship_symbol, action).await,
+        Command::Market { waypoint_symbol } => {
+            let market = client
+                .get_market(system_of(&waypoint_symbol), &waypoint_symbol)
+                .await;
+            let goods = market.trade_goods.unwrap_or_default();
+            if goods.is_empty() {
+                println!("No prices at {waypoint_symbol}, a ship has to be there to see them");
+            }
+            for good in goods {
+                println!(
+                    "{} {:?}: buy {}, sell {}, volume {}",
+                    good.symbol.to_string(),
+                    good.supply,
+                    good.purchase_price,
+                    good.sell_price,
+                    good.trade_volume
+                );
+            }
+        }
+        Command::Contracts => {
+            for contract in client.get_contracts().await {
+                println!(
+                    "{} {:?} accepted={} fulfilled={} deadline {}, paying {} + {}",
+                    contract.id,
+                    contract.r#type,
+                    contract.accepted,
+                    contract.fulfilled,
+                    contract.terms.deadline,
+                    contract.terms.payment.on_accepted,
+                    contract.terms.payment.on_fulfilled
+                );
+                for delivery in contract.terms.deliver.iter().flatten() {
+                    println!(
+                        "  {} to {}: {}/{}",
+                        delivery.trade_symbol,
+                        delivery.destination_symbol,
+                        delivery.units_fulfilled,
+                        delivery.units_required
+                    );
+                }
+            }
+        }
+        Command::BuyShip { ship_type, system } => {
+            let system = match system {
+                Some(system) => system,
+                None => command_ship(&client.get_my_ships().await)
+                    .expect("No command ship to find the system from")
+                    .nav
+                    .system_symbol
+                    .to_owned(),
+            };
+
+            let manager = ManagerFactory::new(&session).get("CLI");
+            match manager.purchase_ship(&system, ship_type).await {
+                Some(ship) => println!("Bought {} at {}", ship.symbol, ship.nav.waypoint_symbol),
+                None => println!("Did not buy a {} in {system}", ship_type.to_string()),
+            }
+        }
+    }
+}
+
+async fn operate_ship(client: &Client, ship_symbol: &str, action: ShipAction) {
+    match action {
+        ShipAction::Navigate { waypoint_symbol } => {
+            match client.depart(ship_symbol, &waypoint_symbol).await {
+                Some(arrival) => {
+                    println!("{ship_symbol} arrives at {waypoint_symbol} at {arrival}")
+                }
+                None => println!("{ship_symbol} is already at {waypoint_symbol}"),
+            }
+        }
+        ShipAction::Dock => {
+            client.dock_ship(ship_symbol).await;
+            println!("{ship_symbol} docked");
+        }
+        ShipAction::Orbit => {
+            client.orbit_ship(ship_symbol).await;
+            println!("{ship_symbol} in orbit");
+        }
+        ShipAction::Sell {
+            trade_symbol,
+            units,
+        } => match (trade_symbol, units) {
+            (Some(trade_symbol), Some(units)) => {
+                client.sell(ship_symbol, trade_symbol, units).await;
+            }
+            _ => client.sell_all(ship_symbol).await,
+        },
+        ShipAction::Refuel => match client.refuel(ship_symbol).await {
+            Result::Ok(r) => println!(
+                "Refuelled {ship_symbol} for {} credits",
+                r.transaction.total_price
+            ),
+            Result::Err(e) => println!("Failed to refuel {ship_symbol}: {e}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn parses_ship_commands() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::parse_from([
+            "trader", "--agent", "mxz", "ship", "MXZ-1", "sell", "iron_ore", "10",
+        ]);
+        assert_eq!(cli.agent.as_deref(), Some("mxz"));
+        match cli.command {
+            Some(Command::Ship {
+                ship_symbol,
+                action:
+                    ShipAction::Sell {
+                        trade_symbol,
+                        units,
+                    },
+            }) => {
+                assert_eq!(ship_symbol, "MXZ-1");
+                assert_eq!(trade_symbol, Some(TradeSymbol::IronOre));
+                assert_eq!(units, Some(10));
+            }
+            _ => panic!("Expected a sell command"),
+        }
+
+        assert_eq!(system_of("X1-ZA40-B7"), "X1-ZA40");
+    }
+}
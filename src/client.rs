@@ -1,31 +1,64 @@
-use std::{fmt::Debug, time::Duration};
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
 
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use spacedust::{
     apis::{
         agents_api,
         configuration::Configuration,
+        contracts_api, default_api,
         fleet_api::{self as fleet},
         systems_api, Error,
     },
     models::{
-        self, Agent, ExtractResourcesRequest, NavigateShipRequest, PurchaseShipRequest,
-        SellCargoRequest, Ship, ShipType,
+        self, market_transaction, AcceptContract200ResponseData, Agent, Construction, Contract,
+        CreateSurvey201ResponseData, DeliverContract200ResponseData, DeliverContractRequest,
+        ExtractResources201ResponseData, ExtractResourcesRequest, GetStatus200Response,
+        InstallMount201ResponseData, InstallMountRequest, JettisonRequest, JumpGate, Market,
+        MarketTransaction, Meta, NavigateShipRequest, PurchaseCargoRequest, PurchaseShipRequest,
+        RefuelShip200ResponseData, RefuelShipRequest, RemoveMount201ResponseData,
+        RemoveMountRequest, SellCargo201ResponseData, SellCargoRequest, Ship, ShipCargo,
+        ShipModificationTransaction, ShipNavStatus, ShipType, Shipyard,
+        SupplyConstruction201ResponseData, SupplyConstructionRequest, Survey, TradeSymbol,
+        TransferCargoRequest,
     },
 };
 
 use log::info;
+use reqwest::StatusCode;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    accounts::Session,
+    alerts::Alerts,
+    cache::{Ttl, CACHE},
+    database::Database,
+    events::{EventKind, EventLog},
+    ledger::{EntryKind, Ledger},
+    metrics,
+};
 
-use serde::{de::DeserializeOwned, Deserialize};
+/// Prices move as we and others trade, so market responses are only reused briefly.
+const MARKET_TTL: Ttl = Ttl::Expires(Duration::from_secs(15));
 
-use crate::configuration::CONFIGURATION;
+const SHIPYARD_TTL: Ttl = Ttl::Expires(Duration::from_secs(60));
 
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(untagged)]
 #[serde(rename_all = "camelCase")]
 pub enum ExtractResourceError {
-    Cooldown { cooldown: CoolDownErrorInner },
+    Cooldown {
+        cooldown: CoolDownErrorInner,
+    },
     Cargo(CargoErrorInner),
+    /// Anything else, e.g. an exhausted or expired survey.
+    Other(serde_json::Value),
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
@@ -56,21 +89,41 @@ pub struct NotSellableErrorInner {
 #[serde(rename_all = "camelCase")]
 pub struct GenericErrorInner<T> {
     code: u16,
-    data: T,
+    pub data: T,
     message: String,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct GenericError<T> {
-    error: GenericErrorInner<T>,
+    pub error: GenericErrorInner<T>,
+    /// The HTTP status the error came with.
+    #[serde(skip)]
+    status: u16,
+}
+
+impl<T> GenericError<T> {
+    /// Whether the error is one play doesn't expect. Breaking a game rule, like selling what the
+    /// market doesn't buy or extracting during a cooldown, comes as a 4xx status with a code from
+    /// 4000 up, and the callers handle it.
+    fn unexpected(&self) -> bool {
+        !(400..500).contains(&self.status)
+            || self.status == StatusCode::TOO_MANY_REQUESTS.as_u16()
+            || self.error.code < 4000
+    }
+}
+
+impl<T> Display for GenericError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code {})", self.error.message, self.error.code)
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct CoolDownErrorInner {
     expiration: String,
-    remaining_seconds: u64,
+    pub remaining_seconds: u64,
     ship_symbol: String,
     total_seconds: u64,
 }
@@ -83,15 +136,23 @@ pub struct CargoErrorInner {
 
 #[derive(Clone)]
 pub struct Client {
-    configuration: &'static Configuration,
+    agent: String,
+    alerts: Alerts,
+    configuration: Arc<Configuration>,
+    database: Database,
+    events: EventLog,
+    ledger: Ledger,
     log_context: String,
 }
 
 impl<T: Debug, U: DeserializeOwned> From<Error<T>> for GenericError<U> {
     fn from(value: Error<T>) -> Self {
         match value {
-            Error::ResponseError(e) => match serde_json::from_str(&e.content) {
-                Result::Ok(v) => v,
+            Error::ResponseError(e) => match serde_json::from_str::<GenericError<U>>(&e.content) {
+                Result::Ok(v) => GenericError {
+                    status: e.status.as_u16(),
+                    ..v
+                },
                 Result::Err(ser_err) => {
                     dbg!(e);
                     dbg!(ser_err);
@@ -104,196 +165,705 @@ impl<T: Debug, U: DeserializeOwned> From<Error<T>> for GenericError<U> {
 }
 
 impl Client {
-    pub fn new(log_context: String) -> Self {
+    pub fn new(log_context: String, session: &Session) -> Self {
         Self {
-            configuration: &CONFIGURATION,
+            agent: session.account.name.to_owned(),
+            alerts: session.alerts.clone(),
+            configuration: session.configuration.clone(),
+            database: session.database.clone(),
+            events: session.events.clone(),
+            ledger: Ledger::new(session),
             log_context,
         }
     }
 
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    pub fn events(&self) -> &EventLog {
+        &self.events
+    }
+
+    fn record_event(&self, ship_symbol: Option<&str>, kind: EventKind, payload: impl Serialize) {
+        let payload = serde_json::to_value(payload).unwrap();
+        self.events.record(&self.agent, ship_symbol, kind, payload);
+    }
+
+    /// Logs the error the game returned for the action the ship attempted.
+    fn record_error<T>(&self, ship_symbol: Option<&str>, action: &str, error: &GenericError<T>) {
+        let payload = json!({
+            "action": action,
+            "code": error.error.code,
+            "message": error.error.message,
+        });
+        self.record_event(ship_symbol, EventKind::Error, payload);
+        if error.unexpected() {
+            self.alerts.api_error(action, &error.to_string());
+        }
+    }
+
+    fn record_modification(
+        &self,
+        ship_symbol: &str,
+        action: &str,
+        transaction: &ShipModificationTransaction,
+    ) {
+        let payload = json!({ "action": action, "transaction": transaction });
+        self.record_event(Some(ship_symbol), EventKind::Modification, payload);
+    }
+
+    fn record_contract(&self, ship_symbol: Option<&str>, action: &str, contract: &Contract) {
+        let payload = json!({ "action": action, "contract": contract });
+        self.record_event(ship_symbol, EventKind::Contract, payload);
+    }
+
     pub async fn get_my_agent(&self) -> Box<Agent> {
-        agents_api::get_my_agent(self.configuration)
+        let agent = agents_api::get_my_agent(&self.configuration)
             .await
             .unwrap()
-            .data
+            .data;
+
+        metrics::record_credits(&self.agent, agent.credits);
+        self.alerts.credits(agent.credits);
+        agent
     }
 
     pub async fn purchase_ship(
         &self,
         ship_type: ShipType,
         waypoint_symbol: &str,
-    ) -> Box<models::Ship> {
-        fleet::purchase_ship(
-            self.configuration,
+    ) -> Result<Box<models::Ship>, GenericError<serde_json::Value>> {
+        let data = fleet::purchase_ship(
+            &self.configuration,
             Some(PurchaseShipRequest::new(
                 ship_type,
                 waypoint_symbol.to_owned(),
             )),
         )
         .await
-        .unwrap()
-        .data
-        .ship
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(None, "purchase ship", e))?
+        .data;
+
+        metrics::record_credits(&self.agent, data.agent.credits);
+        self.database.record_ship_purchase(&data.transaction);
+        self.ledger.ship_purchase(&data.transaction);
+        self.database.save_ship(&data.ship);
+        self.record_event(
+            Some(&data.ship.symbol),
+            EventKind::ShipPurchase,
+            &data.transaction,
+        );
+        Ok(data.ship)
+    }
+
+    pub async fn get_status(&self) -> GetStatus200Response {
+        default_api::get_status(&self.configuration).await.unwrap()
+    }
+
+    /// Clears cached responses, and the saved universe, if the server reset since we last
+    /// checked. Returns the server status it checked.
+    pub async fn observe_reset(&self) -> GetStatus200Response {
+        let status = self.get_status().await;
+
+        CACHE.observe_reset(&status.reset_date);
+        self.database.observe_reset(&status.reset_date);
+        status
+    }
+
+    /// Whether the server still accepts our token. It stops when the server resets.
+    pub async fn token_valid(&self) -> bool {
+        match agents_api::get_my_agent(&self.configuration).await {
+            Result::Err(Error::ResponseError(e)) => e.status != StatusCode::UNAUTHORIZED,
+            _ => true,
+        }
+    }
+
+    /// Saves, logs and accounts for the transaction, and forgets the market's cached prices it
+    /// just moved.
+    fn record_trade(&self, transaction: &MarketTransaction, kind: EntryKind, credits: i64) {
+        CACHE.invalidate(&self.cache_key("market", &transaction.waypoint_symbol));
+        self.database.record_market_transaction(transaction);
+        self.ledger.market(transaction, kind);
+        self.record_event(
+            Some(&transaction.ship_symbol),
+            EventKind::Trade,
+            transaction,
+        );
+
+        metrics::record_credits(&self.agent, credits);
+        if transaction.r#type == market_transaction::Type::Sell {
+            metrics::record_sale(
+                &self.agent,
+                &transaction.waypoint_symbol,
+                &transaction.trade_symbol,
+                transaction.total_price,
+            );
+        }
+    }
+
+    /// The key to cache what the agent sees of the waypoint under. Markets and shipyards show
+    /// prices only to agents with a ship there, so every agent caches its own view.
+    fn cache_key(&self, kind: &str, waypoint_symbol: &str) -> String {
+        format!("{}/{kind}/{waypoint_symbol}", self.agent)
+    }
+
+    /// Forgets the waypoint's market and shipyard if they were cached before one of our ships
+    /// got there, without the prices it now gets to see.
+    fn forget_unpriced(&self, waypoint_symbol: &str) {
+        let market = self.cache_key("market", waypoint_symbol);
+        if CACHE
+            .get::<Box<Market>>(&market, Ttl::Static)
+            .is_some_and(|m| m.trade_goods.is_none())
+        {
+            CACHE.invalidate(&market);
+        }
+
+        let shipyard = self.cache_key("shipyard", waypoint_symbol);
+        if CACHE
+            .get::<Box<Shipyard>>(&shipyard, Ttl::Static)
+            .is_some_and(|s| s.ships.is_none())
+        {
+            CACHE.invalidate(&shipyard);
+        }
     }
 
+    /// The cached response for `key` if it is still valid, otherwise awaits `fetch` and caches
+    /// its result.
+    async fn cached<T: Clone + Send + 'static>(
+        key: String,
+        ttl: Ttl,
+        fetch: impl Future<Output = T>,
+    ) -> T {
+        if let Some(value) = CACHE.get(&key, ttl) {
+            return value;
+        }
+
+        let value = fetch.await;
+        CACHE.insert(&key, &value);
+        value
+    }
+
+    /// Every waypoint of the system, going through all the pages of them.
     pub async fn get_system_waypoints(&self, system_name: &str) -> Vec<models::Waypoint> {
-        systems_api::get_system_waypoints(self.configuration, system_name, None, None, None, None)
-            .await
-            .unwrap()
-            .data
+        let fetch = async {
+            let mut waypoints = Vec::new();
+            for page in 1.. {
+                let response = systems_api::get_system_waypoints(
+                    &self.configuration,
+                    system_name,
+                    Some(page),
+                    Some(PAGE_LIMIT),
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+
+                let last = is_last_page(&response.meta, response.data.len());
+                waypoints.extend(response.data);
+                if last {
+                    break;
+                }
+            }
+            waypoints
+        };
+        Self::cached(format!("waypoints/{system_name}"), Ttl::Static, fetch).await
+    }
+
+    pub async fn get_shipyard(&self, system_symbol: &str, waypoint_symbol: &str) -> Box<Shipyard> {
+        let fetch = async {
+            systems_api::get_shipyard(&self.configuration, system_symbol, waypoint_symbol)
+                .await
+                .unwrap()
+                .data
+        };
+        Self::cached(
+            self.cache_key("shipyard", waypoint_symbol),
+            SHIPYARD_TTL,
+            fetch,
+        )
+        .await
+    }
+
+    pub async fn get_jump_gate(&self, system_symbol: &str, waypoint_symbol: &str) -> Box<JumpGate> {
+        let fetch = async {
+            systems_api::get_jump_gate(&self.configuration, system_symbol, waypoint_symbol)
+                .await
+                .unwrap()
+                .data
+        };
+        Self::cached(format!("jump-gate/{waypoint_symbol}"), Ttl::Static, fetch).await
     }
 
     pub async fn get_my_ships(&self) -> Vec<Ship> {
-        fleet::get_my_ships(self.configuration, None, None)
+        let ships = fleet::get_my_ships(&self.configuration, None, None)
             .await
             .unwrap()
-            .data
+            .data;
+
+        ships.iter().for_each(|s| self.database.save_ship(s));
+        ships
     }
 
     pub async fn get_ship(&self, ship_symbol: &str) -> Box<Ship> {
-        fleet::get_my_ship(self.configuration, ship_symbol)
+        let ship = fleet::get_my_ship(&self.configuration, ship_symbol)
             .await
             .unwrap()
-            .data
+            .data;
+
+        self.database.save_ship(&ship);
+        ship
     }
 
     pub async fn dock_ship(&self, ship_symbol: &str) {
-        fleet::dock_ship(self.configuration, ship_symbol)
+        fleet::dock_ship(&self.configuration, ship_symbol)
             .await
             .unwrap();
     }
 
-    pub async fn navigate(&self, ship_symbol: &str, waypoint_symbol: &str) {
-        let resp = fleet::navigate_ship(
-            self.configuration,
+    /// Sets the ship on its way to the waypoint, undocking first if needed, without waiting for
+    /// it to get there. Returns when the ship will be free to act again: its arrival, or the end
+    /// of the trip it is already on. Returns None if the ship is already at the waypoint.
+    pub async fn depart(&self, ship_symbol: &str, waypoint_symbol: &str) -> Option<DateTime<Utc>> {
+        let ship = self.get_ship(ship_symbol).await;
+        if ship.nav.status == ShipNavStatus::InTransit {
+            return Some(parse_time(&ship.nav.route.arrival));
+        }
+
+        if ship.nav.waypoint_symbol == waypoint_symbol {
+            self.forget_unpriced(waypoint_symbol);
+            return None;
+        }
+
+        if ship.nav.status == ShipNavStatus::Docked {
+            self.orbit_ship(ship_symbol).await;
+        }
+
+        let nav = fleet::navigate_ship(
+            &self.configuration,
             ship_symbol,
             Some(NavigateShipRequest::new(waypoint_symbol.to_owned())),
         )
         .await
         .unwrap()
-        .data;
-
-        dbg!(&resp);
-
-        let route = resp.nav.route;
-        let departure = DateTime::parse_from_rfc3339(route.departure_time.as_str()).unwrap();
-        let arrival = DateTime::parse_from_rfc3339(route.arrival.as_str()).unwrap();
-
-        let eta = (arrival - departure).num_seconds() as u64;
+        .data
+        .nav;
+        self.record_event(Some(ship_symbol), EventKind::Navigation, &nav);
 
-        info!("[{ship_symbol}] Travelling to {waypoint_symbol}, sleeping {eta}");
-        tokio::time::sleep(Duration::from_secs(eta)).await;
+        let arrival = parse_time(&nav.route.arrival);
+        info!("[{ship_symbol}] Travelling to {waypoint_symbol}, arriving at {arrival}");
+        Some(arrival)
     }
 
     pub async fn orbit_ship(&self, ship_symbol: &str) {
-        fleet::orbit_ship(self.configuration, ship_symbol)
+        fleet::orbit_ship(&self.configuration, ship_symbol)
             .await
             .unwrap();
     }
 
     pub async fn sell_all(&self, ship_symbol: &str) {
-        let cargo = fleet::get_my_ship_cargo(self.configuration, ship_symbol)
+        let cargo = fleet::get_my_ship_cargo(&self.configuration, ship_symbol)
             .await
             .unwrap();
 
         for c in cargo.data.inventory {
-            let resp = fleet::sell_cargo(
-                self.configuration,
-                ship_symbol,
-                Some(SellCargoRequest::new(c.symbol, c.units)),
-            )
-            .await;
-            match resp {
-                Result::Ok(a) => {
-                    let transaction = a.data.transaction;
-
-                    let context = &self.log_context;
-                    info!(
-                        "[{context}] Sold {}x{} for {} credits. Total credits={}",
-                        transaction.units,
-                        transaction.trade_symbol.as_str(),
-                        transaction.total_price,
-                        a.data.agent.credits
-                    )
-                }
-                Result::Err(e) => {
-                    let err: GenericError<SellCargoError> = e.into();
-                    let context = &self.log_context;
-                    match err.error.data {
-                        SellCargoError::NotFoundError(cargo) => {
-                            info!(
-                                "[{context}] Failed to sell cargo. Tried to sell {}x{} but had {}x{}",
-                                cargo.units_to_remove,
-                                cargo.trade_symbol,
-                                cargo.cargo_units,
-                                cargo.trade_symbol
-                            )
-                        }
-                        SellCargoError::NotSellableError(sell) => {
-                            info!(
-                                "[{context}] Failed to sell {}x{} as is not sellable in this market",
-                                c.units,
-                                sell.trade_symbol,
-                            )
-                        }
+            self.sell(ship_symbol, c.symbol, c.units).await;
+        }
+    }
+
+    /// Sells the units at the market the ship is docked at. Returns whether the sale went through.
+    pub async fn sell(&self, ship_symbol: &str, trade_symbol: TradeSymbol, units: i32) -> bool {
+        let resp = fleet::sell_cargo(
+            &self.configuration,
+            ship_symbol,
+            Some(SellCargoRequest::new(trade_symbol, units)),
+        )
+        .await;
+        match resp {
+            Result::Ok(a) => {
+                let transaction = a.data.transaction;
+                self.record_trade(&transaction, EntryKind::Trade, a.data.agent.credits);
+
+                let context = &self.log_context;
+                info!(
+                    "[{context}] Sold {}x{} for {} credits. Total credits={}",
+                    transaction.units,
+                    transaction.trade_symbol.as_str(),
+                    transaction.total_price,
+                    a.data.agent.credits
+                );
+                true
+            }
+            Result::Err(e) => {
+                let err: GenericError<SellCargoError> = e.into();
+                self.record_error(Some(ship_symbol), "sell", &err);
+                let context = &self.log_context;
+                match err.error.data {
+                    SellCargoError::NotFoundError(cargo) => {
+                        info!(
+                            "[{context}] Failed to sell cargo. Tried to sell {}x{} but had {}x{}",
+                            cargo.units_to_remove,
+                            cargo.trade_symbol,
+                            cargo.cargo_units,
+                            cargo.trade_symbol
+                        )
+                    }
+                    SellCargoError::NotSellableError(sell) => {
+                        info!(
+                            "[{context}] Failed to sell {}x{} as is not sellable in this market",
+                            units, sell.trade_symbol,
+                        )
                     }
                 }
+                false
             }
         }
     }
 
-    pub async fn extract_till_full(&self, ship_symbol: &str) {
-        loop {
-            let extracted = fleet::extract_resources(
-                self.configuration,
-                ship_symbol,
-                Some(ExtractResourcesRequest::new()),
-            )
-            .await;
+    pub async fn get_ship_cargo(&self, ship_symbol: &str) -> Box<ShipCargo> {
+        fleet::get_my_ship_cargo(&self.configuration, ship_symbol)
+            .await
+            .unwrap()
+            .data
+    }
 
-            match extracted {
-                Result::Ok(r) => {
-                    let cargo = r.data.cargo;
-                    let units = cargo.units;
-                    let capacity = cargo.capacity;
+    pub async fn get_market(&self, system_symbol: &str, waypoint_symbol: &str) -> Box<Market> {
+        let fetch = async {
+            systems_api::get_market(&self.configuration, system_symbol, waypoint_symbol)
+                .await
+                .unwrap()
+                .data
+        };
+        Self::cached(self.cache_key("market", waypoint_symbol), MARKET_TTL, fetch).await
+    }
 
-                    let yld = r.data.extraction.r#yield;
-                    let yld_symbol = yld.symbol.to_string();
-                    let yld_units = yld.units;
+    pub async fn purchase_cargo(
+        &self,
+        ship_symbol: &str,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    ) -> Result<Box<SellCargo201ResponseData>, GenericError<serde_json::Value>> {
+        fleet::purchase_cargo(
+            &self.configuration,
+            ship_symbol,
+            Some(PurchaseCargoRequest::new(trade_symbol, units)),
+        )
+        .await
+        .map(|r| r.data)
+        .inspect(|d| self.record_trade(&d.transaction, EntryKind::Trade, d.agent.credits))
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(ship_symbol), "purchase", e))
+    }
 
-                    let sleep_seconds = r.data.cooldown.remaining_seconds as u64;
+    pub async fn jettison(&self, ship_symbol: &str, trade_symbol: TradeSymbol, units: i32) {
+        fleet::jettison(
+            &self.configuration,
+            ship_symbol,
+            Some(JettisonRequest::new(trade_symbol, units)),
+        )
+        .await
+        .unwrap();
 
-                    info!("[{ship_symbol}] extraction cooldown, yield={yld_units}x{yld_symbol}, inventory={units}/{capacity}, sleeping for {sleep_seconds} seconds");
+        let payload = json!({ "action": "jettison", "tradeSymbol": trade_symbol, "units": units });
+        self.record_event(Some(ship_symbol), EventKind::Cargo, payload);
+    }
 
-                    if capacity - units < 3 {
-                        return;
-                    }
+    pub async fn install_mount(
+        &self,
+        ship_symbol: &str,
+        mount_symbol: TradeSymbol,
+    ) -> Result<Box<InstallMount201ResponseData>, GenericError<serde_json::Value>> {
+        fleet::install_mount(
+            &self.configuration,
+            ship_symbol,
+            Some(InstallMountRequest::new(mount_symbol.to_string())),
+        )
+        .await
+        .map(|r| r.data)
+        .inspect(|d| {
+            self.ledger.modification(&d.transaction);
+            self.record_modification(ship_symbol, "install mount", &d.transaction);
+        })
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(ship_symbol), "install mount", e))
+    }
 
-                    tokio::time::sleep(Duration::from_secs(sleep_seconds)).await;
-                }
-                Result::Err(e) => {
-                    let err: GenericError<ExtractResourceError> = e.into();
+    pub async fn remove_mount(
+        &self,
+        ship_symbol: &str,
+        mount_symbol: TradeSymbol,
+    ) -> Result<Box<RemoveMount201ResponseData>, GenericError<serde_json::Value>> {
+        fleet::remove_mount(
+            &self.configuration,
+            ship_symbol,
+            Some(RemoveMountRequest::new(mount_symbol.to_string())),
+        )
+        .await
+        .map(|r| r.data)
+        .inspect(|d| {
+            self.ledger.modification(&d.transaction);
+            self.record_modification(ship_symbol, "remove mount", &d.transaction);
+        })
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(ship_symbol), "remove mount", e))
+    }
 
-                    let sleep_seconds = match err.error.data {
-                        ExtractResourceError::Cooldown { cooldown } => cooldown.remaining_seconds,
-                        ExtractResourceError::Cargo { .. } => return,
-                    };
+    pub async fn refuel(
+        &self,
+        ship_symbol: &str,
+    ) -> Result<Box<RefuelShip200ResponseData>, GenericError<serde_json::Value>> {
+        fleet::refuel_ship(
+            &self.configuration,
+            ship_symbol,
+            Some(RefuelShipRequest::new()),
+        )
+        .await
+        .map(|r| r.data)
+        .inspect(|d| self.record_trade(&d.transaction, EntryKind::Refuel, d.agent.credits))
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(ship_symbol), "refuel", e))
+    }
 
-                    info!(
-                        "[{ship_symbol}] extraction cooldown, sleeping for {sleep_seconds} seconds"
-                    );
+    pub async fn get_contracts(&self) -> Vec<Contract> {
+        let contracts = contracts_api::get_contracts(&self.configuration, None, Some(20))
+            .await
+            .unwrap()
+            .data;
 
-                    tokio::time::sleep(Duration::from_secs(sleep_seconds)).await;
-                }
-            };
+        contracts
+            .iter()
+            .for_each(|c| self.database.save_contract(c));
+        contracts
+    }
+
+    pub async fn accept_contract(
+        &self,
+        contract_id: &str,
+    ) -> Result<Box<AcceptContract200ResponseData>, GenericError<serde_json::Value>> {
+        contracts_api::accept_contract(&self.configuration, contract_id)
+            .await
+            .map(|r| r.data)
+            .inspect(|d| {
+                self.database.save_contract(&d.contract);
+                self.record_contract(None, "accept", &d.contract);
+            })
+            .map_err(|e| e.into())
+            .inspect_err(|e| self.record_error(None, "accept contract", e))
+    }
+
+    pub async fn negotiate_contract(
+        &self,
+        ship_symbol: &str,
+    ) -> Result<Box<Contract>, GenericError<serde_json::Value>> {
+        fleet::negotiate_contract(&self.configuration, ship_symbol)
+            .await
+            .map(|r| r.data.contract)
+            .inspect(|c| {
+                self.database.save_contract(c);
+                self.record_contract(Some(ship_symbol), "negotiate", c);
+            })
+            .map_err(|e| e.into())
+            .inspect_err(|e| self.record_error(Some(ship_symbol), "negotiate contract", e))
+    }
+
+    pub async fn deliver_contract(
+        &self,
+        contract_id: &str,
+        ship_symbol: &str,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    ) -> Result<Box<DeliverContract200ResponseData>, GenericError<serde_json::Value>> {
+        contracts_api::deliver_contract(
+            &self.configuration,
+            contract_id,
+            Some(DeliverContractRequest::new(
+                ship_symbol.to_owned(),
+                trade_symbol.to_string(),
+                units,
+            )),
+        )
+        .await
+        .map(|r| r.data)
+        .inspect(|d| {
+            self.database.save_contract(&d.contract);
+            self.ledger
+                .contract_delivery(contract_id, ship_symbol, units);
+            self.record_contract(Some(ship_symbol), "deliver", &d.contract);
+        })
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(ship_symbol), "deliver contract", e))
+    }
+
+    pub async fn fulfill_contract(
+        &self,
+        contract_id: &str,
+    ) -> Result<Box<AcceptContract200ResponseData>, GenericError<serde_json::Value>> {
+        contracts_api::fulfill_contract(&self.configuration, contract_id)
+            .await
+            .map(|r| r.data)
+            .inspect(|d| {
+                self.database.save_contract(&d.contract);
+                self.ledger.contract_fulfilled(&d.contract);
+                self.record_contract(None, "fulfill", &d.contract);
+            })
+            .map_err(|e| e.into())
+            .inspect_err(|e| self.record_error(None, "fulfill contract", e))
+    }
+
+    pub async fn get_construction(
+        &self,
+        system_symbol: &str,
+        waypoint_symbol: &str,
+    ) -> Box<Construction> {
+        systems_api::get_construction(&self.configuration, system_symbol, waypoint_symbol)
+            .await
+            .unwrap()
+            .data
+    }
+
+    pub async fn supply_construction(
+        &self,
+        ship_symbol: &str,
+        system_symbol: &str,
+        waypoint_symbol: &str,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    ) -> Result<Box<SupplyConstruction201ResponseData>, GenericError<serde_json::Value>> {
+        systems_api::supply_construction(
+            &self.configuration,
+            system_symbol,
+            waypoint_symbol,
+            Some(SupplyConstructionRequest::new(
+                ship_symbol.to_owned(),
+                trade_symbol.to_string(),
+                units,
+            )),
+        )
+        .await
+        .map(|r| r.data)
+        .inspect(|d| {
+            let payload = json!({
+                "tradeSymbol": trade_symbol,
+                "units": units,
+                "construction": d.construction,
+            });
+            self.record_event(Some(ship_symbol), EventKind::Construction, payload);
+        })
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(ship_symbol), "supply construction", e))
+    }
+
+    /// Runs a single extraction, using the survey if given. The cooldown is left to the caller.
+    pub async fn extract_once(
+        &self,
+        ship_symbol: &str,
+        survey: Option<Survey>,
+    ) -> Result<Box<ExtractResources201ResponseData>, GenericError<ExtractResourceError>> {
+        let result = match survey {
+            Some(s) => {
+                fleet::extract_resources_with_survey(&self.configuration, ship_symbol, Some(s))
+                    .await
+                    .map(|r| r.data)
+                    .map_err(GenericError::from)
+            }
+            None => fleet::extract_resources(
+                &self.configuration,
+                ship_symbol,
+                Some(ExtractResourcesRequest::new()),
+            )
+            .await
+            .map(|r| r.data)
+            .map_err(GenericError::from),
+        };
+
+        match &result {
+            Result::Ok(data) => {
+                let extracted = &data.extraction.r#yield;
+                metrics::record_extraction(
+                    &self.agent,
+                    &extracted.symbol.to_string(),
+                    extracted.units,
+                );
+                self.record_event(Some(ship_symbol), EventKind::Extraction, &data.extraction);
+            }
+            Result::Err(e) => self.record_error(Some(ship_symbol), "extract", e),
         }
+        result
+    }
+
+    pub async fn create_survey(
+        &self,
+        ship_symbol: &str,
+    ) -> Result<Box<CreateSurvey201ResponseData>, GenericError<serde_json::Value>> {
+        fleet::create_survey(&self.configuration, ship_symbol)
+            .await
+            .map(|r| r.data)
+            .inspect(|d| self.record_event(Some(ship_symbol), EventKind::Survey, &d.surveys))
+            .map_err(|e| e.into())
+            .inspect_err(|e| self.record_error(Some(ship_symbol), "survey", e))
+    }
+
+    pub async fn transfer_cargo(
+        &self,
+        from_ship_symbol: &str,
+        to_ship_symbol: &str,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    ) -> Result<Box<ShipCargo>, GenericError<serde_json::Value>> {
+        fleet::transfer_cargo(
+            &self.configuration,
+            from_ship_symbol,
+            Some(TransferCargoRequest::new(
+                trade_symbol,
+                units,
+                to_ship_symbol.to_owned(),
+            )),
+        )
+        .await
+        .map(|r| r.data.cargo)
+        .inspect(|_| {
+            let payload = json!({
+                "action": "transfer",
+                "to": to_ship_symbol,
+                "tradeSymbol": trade_symbol,
+                "units": units,
+            });
+            self.record_event(Some(from_ship_symbol), EventKind::Cargo, payload);
+        })
+        .map_err(|e| e.into())
+        .inspect_err(|e| self.record_error(Some(from_ship_symbol), "transfer cargo", e))
     }
 }
 
+/// The most items the API returns in a page.
+const PAGE_LIMIT: i32 = 20;
+
+/// Whether the page, holding that many items, is the last one.
+fn is_last_page(meta: &Meta, items: usize) -> bool {
+    items == 0 || meta.page * meta.limit >= meta.total
+}
+
+pub fn parse_time(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn pages_run_out_at_the_total() {
+        assert!(!is_last_page(&Meta::new(45, 1, 20), 20));
+        assert!(!is_last_page(&Meta::new(45, 2, 20), 20));
+        assert!(is_last_page(&Meta::new(45, 3, 20), 5));
+        assert!(is_last_page(&Meta::new(40, 2, 20), 20));
+        assert!(is_last_page(&Meta::new(45, 4, 20), 0));
+    }
+
     #[test]
     fn deserialise_sell_cargo_error_not_sellable() {
         let str = "{\"error\":{\"message\":\"Market sell failed. Trade good ANTIMATTER is not available at X1-ZA40-15970B.\",\"code\":4602,\"data\":{\"waypointSymbol\":\"X1-ZA40-15970B\",\"tradeSymbol\":\"ANTIMATTER\"}}}";
@@ -331,6 +901,7 @@ mod tests {
         match err.error.data {
             ExtractResourceError::Cargo { .. } => (),
             ExtractResourceError::Cooldown { .. } => panic!(),
+            ExtractResourceError::Other(_) => panic!(),
         }
     }
 
@@ -345,6 +916,27 @@ mod tests {
             ExtractResourceError::Cooldown { cooldown, .. } => {
                 assert_eq!(cooldown.remaining_seconds, 5);
             }
+            ExtractResourceError::Other(_) => panic!(),
         }
     }
+
+    #[test]
+    fn only_unexpected_errors_count_towards_alerts() {
+        let error = |status, code| -> GenericError<serde_json::Value> {
+            Error::<()>::ResponseError(spacedust::apis::ResponseContent {
+                status,
+                content: format!(
+                    "{{\"error\":{{\"message\":\"\",\"code\":{code},\"data\":{{}}}}}}"
+                ),
+                entity: None,
+            })
+            .into()
+        };
+
+        assert!(!error(StatusCode::BAD_REQUEST, 4602).unexpected());
+        assert!(!error(StatusCode::CONFLICT, 4000).unexpected());
+        assert!(error(StatusCode::TOO_MANY_REQUESTS, 429).unexpected());
+        assert!(error(StatusCode::BAD_GATEWAY, 4602).unexpected());
+        assert!(error(StatusCode::UNPROCESSABLE_ENTITY, 422).unexpected());
+    }
 }
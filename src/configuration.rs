@@ -1,14 +1,14 @@
-use std::env;
+use std::{env, fmt::Display, str::FromStr};
 
 use dotenv::dotenv;
-use lazy_static::lazy_static;
+use log::info;
 use reqwest::{header::HeaderValue, ClientBuilder, Request, Response};
 use reqwest_middleware::{Middleware, Next, Result};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use spacedust::apis::configuration::Configuration;
 use task_local_extensions::Extensions;
 
-use crate::limiter::RateLimiter;
+use crate::{cassette::CassetteMiddleware, limiter::RateLimiter, metrics::MetricsMiddleware};
 
 pub struct ContentLengthFixMiddleware;
 
@@ -32,27 +32,66 @@ impl Middleware for ContentLengthFixMiddleware {
 pub struct ConfigurationFactory {}
 
 impl ConfigurationFactory {
-    pub fn get_config(token: &str) -> Configuration {
+    /// A configuration with its own rate limiter, reporting metrics under the agent's name.
+    /// Requests are recorded to a cassette, or answered from one, when asked to.
+    pub fn get_config(agent: &str, token: &str) -> Configuration {
+        Configuration {
+            bearer_access_token: Some(token.to_owned()),
+            ..Self::get_anonymous_config(agent)
+        }
+    }
+
+    /// Like [`ConfigurationFactory::get_config`], for the calls made before the agent has a
+    /// token, like registering it.
+    pub fn get_anonymous_config(agent: &str) -> Configuration {
         let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
+        let cassette = CassetteMiddleware::from_env(agent);
 
-        let client = reqwest_middleware::ClientBuilder::new(ClientBuilder::new().build().unwrap())
-            .with(RateLimiter::new())
+        let mut builder =
+            reqwest_middleware::ClientBuilder::new(ClientBuilder::new().build().unwrap());
+        // Replayed responses skip the rate limiter and retries, recorded ones are what the
+        // server actually sent
+        let cassette = match cassette {
+            Some(cassette) if cassette.replaying() => {
+                builder = builder.with(cassette);
+                None
+            }
+            cassette => cassette,
+        };
+        builder = builder
+            .with(RateLimiter::new(agent))
             .with(RetryTransientMiddleware::new_with_policy(retry_policy))
             .with(ContentLengthFixMiddleware)
-            .build();
-
+            .with(MetricsMiddleware::new(agent));
+        if let Some(cassette) = cassette {
+            builder = builder.with(cassette);
+        }
         Configuration {
-            bearer_access_token: Some(token.to_owned()),
-            client,
+            client: builder.build(),
             ..Default::default()
         }
     }
 }
 
-lazy_static! {
-    // having a static singleton configuration means that the throttling will apply to any client we instantiate
-    pub static ref CONFIGURATION: Configuration = {
-        dotenv().ok();
-        ConfigurationFactory::get_config(env::var("TOKEN").unwrap().as_str())
-    };
+/// Reads an optional setting from the environment (or `.env`).
+pub fn optional_var(key: &str) -> Option<String> {
+    dotenv().ok();
+    env::var(key).ok()
+}
+
+/// Parses the value read for the setting, logging and ignoring one that doesn't parse so the
+/// caller falls back on its default.
+pub fn parse_var<T>(key: &str, value: Option<String>) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = value?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            info!("[CONFIG] Ignoring {key}={value}: {e}");
+            None
+        }
+    }
 }
@@ -0,0 +1,600 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use spacedust::models::{Construction, TradeSymbol, Waypoint, WaypointTraitSymbol, WaypointType};
+
+use crate::{
+    behaviours::SavedBehaviour,
+    client::ExtractResourceError,
+    contracts::held_units,
+    manager::Manager,
+    scheduler::{Behaviour, Next, Reason},
+};
+
+/// Where the construction ship is in sourcing and delivering a batch of material.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+enum ConstructionState {
+    Planning,
+    #[serde(rename_all = "camelCase")]
+    Buying {
+        market: String,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Mining {
+        field: String,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Extracting {
+        trade_symbol: TradeSymbol,
+        units: i32,
+    },
+    #[serde(rename_all = "camelCase")]
+    Delivering {
+        trade_symbol: TradeSymbol,
+        units: i32,
+    },
+}
+
+/// What came of one extraction for the construction.
+enum Extraction {
+    CoolingDown(u64),
+    Holding(i32),
+}
+
+/// Supplies the materials required by a waypoint under construction (typically the system's
+/// jump gate), buying them from markets in the system or mining them when nobody sells them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConstructionProject {
+    system_symbol: String,
+    waypoint_symbol: String,
+    state: ConstructionState,
+    spent: i64,
+    supplied: HashMap<TradeSymbol, i32>,
+}
+
+impl ConstructionProject {
+    pub fn new(system_symbol: &str, waypoint_symbol: &str) -> Self {
+        Self {
+            system_symbol: system_symbol.to_owned(),
+            waypoint_symbol: waypoint_symbol.to_owned(),
+            state: ConstructionState::Planning,
+            spent: 0,
+            supplied: HashMap::new(),
+        }
+    }
+
+    /// Resumes the project saved for the ship if it supplies the same site, otherwise starts
+    /// a new one.
+    pub fn resume(
+        saved: Option<SavedBehaviour>,
+        system_symbol: &str,
+        waypoint_symbol: &str,
+    ) -> Self {
+        match saved {
+            Some(SavedBehaviour::Construction(project))
+                if project.waypoint_symbol == waypoint_symbol =>
+            {
+                info!(
+                    "[{waypoint_symbol}] Resuming construction, spent {} credits so far",
+                    project.spent
+                );
+                project
+            }
+            _ => Self::new(system_symbol, waypoint_symbol),
+        }
+    }
+
+    pub async fn outstanding(&self, manager: &Manager) -> Vec<(TradeSymbol, i32)> {
+        let construction = manager
+            .client()
+            .get_construction(&self.system_symbol, &self.waypoint_symbol)
+            .await;
+
+        outstanding_materials(&construction)
+    }
+
+    /// Takes the next step in sourcing and delivering a batch of the most needed material with
+    /// the given ship.
+    pub async fn supply_once(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        match self.state.clone() {
+            ConstructionState::Planning => self.plan(manager, ship_symbol).await,
+            ConstructionState::Buying {
+                market,
+                trade_symbol,
+                units,
+            } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, &market).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                let bought = self
+                    .buy(manager, ship_symbol, &market, trade_symbol, units)
+                    .await;
+                self.acquired(ship_symbol, trade_symbol, bought)
+            }
+            ConstructionState::Mining {
+                field,
+                trade_symbol,
+                units,
+            } => {
+                if let Some(arrival) = manager.client().depart(ship_symbol, &field).await {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                manager.client().orbit_ship(ship_symbol).await;
+                clear_hold(manager, ship_symbol, trade_symbol, false).await;
+                self.state = ConstructionState::Extracting {
+                    trade_symbol,
+                    units,
+                };
+                Next::now()
+            }
+            ConstructionState::Extracting {
+                trade_symbol,
+                units,
+            } => match mine_once(manager, ship_symbol, trade_symbol, units).await {
+                Extraction::CoolingDown(seconds) => Next::after(seconds, Reason::Cooldown),
+                Extraction::Holding(held) => self.acquired(ship_symbol, trade_symbol, held),
+            },
+            ConstructionState::Delivering {
+                trade_symbol,
+                units,
+            } => {
+                if let Some(arrival) = manager
+                    .client()
+                    .depart(ship_symbol, &self.waypoint_symbol)
+                    .await
+                {
+                    return Next::Wake {
+                        at: arrival,
+                        reason: Reason::Arrival,
+                    };
+                }
+
+                self.state = ConstructionState::Planning;
+                match self
+                    .deliver(manager, ship_symbol, trade_symbol, units)
+                    .await
+                {
+                    Some(true) => {
+                        self.report_complete(ship_symbol);
+                        Next::Done
+                    }
+                    Some(false) => Next::now(),
+                    // Planning delivers the goods still in the hold first
+                    None => Next::after(60, Reason::Ready),
+                }
+            }
+        }
+    }
+
+    /// Picks the most needed material and where to source it.
+    async fn plan(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        let context = ship_symbol;
+
+        let outstanding = self.outstanding(manager).await;
+        let Some((trade_symbol, units)) = outstanding.first().cloned() else {
+            self.report_complete(ship_symbol);
+            return Next::Done;
+        };
+
+        // Deliver what the hold already carries before sourcing more
+        let cargo = manager.client().get_ship_cargo(ship_symbol).await;
+        if let Some((held_symbol, held)) = outstanding.iter().find_map(|&(s, needed)| {
+            let held = held_units(&cargo.inventory, s);
+            (held > 0).then_some((s, i32::min(held, needed)))
+        }) {
+            info!(
+                "[{context}] Already holding {held}x{} for the construction",
+                held_symbol.to_string()
+            );
+            self.state = ConstructionState::Delivering {
+                trade_symbol: held_symbol,
+                units: held,
+            };
+            return Next::now();
+        }
+
+        info!(
+            "[{context}] Construction at {} needs {}, sourcing {units}x{}",
+            self.waypoint_symbol,
+            outstanding
+                .iter()
+                .map(|(s, u)| format!("{u}x{}", s.to_string()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            trade_symbol.to_string()
+        );
+
+        if let Some(market) = self.find_market_selling(manager, trade_symbol).await {
+            self.state = ConstructionState::Buying {
+                market: market.symbol,
+                trade_symbol,
+                units,
+            };
+            return Next::now();
+        }
+
+        let Some(field) = manager
+            .find_waypoint_for_type(&self.system_symbol, WaypointType::AsteroidField)
+            .await
+        else {
+            info!(
+                "[{context}] No asteroid field to mine in {}",
+                self.system_symbol
+            );
+            return self.acquired(ship_symbol, trade_symbol, 0);
+        };
+
+        self.state = ConstructionState::Mining {
+            field: field.symbol,
+            trade_symbol,
+            units,
+        };
+        Next::now()
+    }
+
+    /// Moves on to delivering what was sourced, or starts over if nothing was.
+    fn acquired(&mut self, ship_symbol: &str, trade_symbol: TradeSymbol, units: i32) -> Next {
+        if units == 0 {
+            info!(
+                "[{ship_symbol}] Could not source any {}, retrying in 60 seconds",
+                trade_symbol.to_string()
+            );
+            self.state = ConstructionState::Planning;
+            return Next::after(60, Reason::Ready);
+        }
+
+        self.state = ConstructionState::Delivering {
+            trade_symbol,
+            units,
+        };
+        Next::now()
+    }
+
+    fn report_complete(&self, ship_symbol: &str) {
+        info!(
+            "[{ship_symbol}] Construction at {} is complete. Spent {} credits, supplied {}",
+            self.waypoint_symbol,
+            self.spent,
+            self.report()
+        );
+    }
+
+    fn report(&self) -> String {
+        self.supplied
+            .iter()
+            .map(|(s, u)| format!("{u}x{}", s.to_string()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    async fn find_market_selling(
+        &self,
+        manager: &Manager,
+        trade_symbol: TradeSymbol,
+    ) -> Option<Waypoint> {
+        let waypoints = manager.system_waypoints(&self.system_symbol).await;
+        let site = waypoints
+            .iter()
+            .find(|w| w.symbol == self.waypoint_symbol)?
+            .clone();
+
+        let mut candidates = Vec::new();
+        for waypoint in waypoints.iter().filter(|w| {
+            w.traits
+                .iter()
+                .any(|t| t.symbol == WaypointTraitSymbol::Marketplace)
+        }) {
+            let market = manager
+                .client()
+                .get_market(&self.system_symbol, &waypoint.symbol)
+                .await;
+
+            if market
+                .exports
+                .iter()
+                .chain(market.exchange.iter())
+                .any(|g| g.symbol == trade_symbol)
+            {
+                candidates.push(waypoint.clone());
+            }
+        }
+
+        candidates
+            .into_iter()
+            .min_by_key(|w| (w.x - site.x).pow(2) + (w.y - site.y).pow(2))
+    }
+
+    /// Buys the units at the market the ship is at, refuelling while docked. Returns the units
+    /// bought.
+    async fn buy(
+        &mut self,
+        manager: &Manager,
+        ship_symbol: &str,
+        market: &str,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    ) -> i32 {
+        let context = ship_symbol;
+        let client = manager.client();
+
+        client.dock_ship(ship_symbol).await;
+        manager.refuel(ship_symbol).await;
+        clear_hold(manager, ship_symbol, trade_symbol, true).await;
+
+        let market_data = client.get_market(&self.system_symbol, market).await;
+        let trade_volume = market_data
+            .trade_goods
+            .unwrap_or_default()
+            .iter()
+            .find(|g| g.symbol == trade_symbol)
+            .map_or(units, |g| g.trade_volume);
+
+        let cargo = client.get_ship_cargo(ship_symbol).await;
+        let mut remaining = i32::min(units, cargo.capacity - cargo.units);
+        let mut bought = 0;
+
+        while remaining > 0 {
+            let batch = i32::min(remaining, trade_volume);
+            match client
+                .purchase_cargo(ship_symbol, trade_symbol, batch)
+                .await
+            {
+                Result::Ok(r) => {
+                    let transaction = r.transaction;
+                    self.spent += transaction.total_price as i64;
+                    bought += transaction.units;
+                    remaining -= transaction.units;
+
+                    info!(
+                        "[{context}] Bought {}x{} for {} credits. Total credits={}",
+                        transaction.units,
+                        transaction.trade_symbol,
+                        transaction.total_price,
+                        r.agent.credits
+                    );
+                }
+                Result::Err(e) => {
+                    info!(
+                        "[{context}] Failed to buy {batch}x{}: {e}",
+                        trade_symbol.to_string()
+                    );
+                    break;
+                }
+            }
+        }
+
+        bought
+    }
+
+    /// Delivers the units to the construction site the ship is at, refuelling while docked.
+    /// Returns whether the construction is complete, or None if the delivery failed and should
+    /// be retried.
+    async fn deliver(
+        &mut self,
+        manager: &Manager,
+        ship_symbol: &str,
+        trade_symbol: TradeSymbol,
+        units: i32,
+    ) -> Option<bool> {
+        let context = ship_symbol;
+        let client = manager.client();
+
+        client.dock_ship(ship_symbol).await;
+        manager.refuel(ship_symbol).await;
+
+        match client
+            .supply_construction(
+                ship_symbol,
+                &self.system_symbol,
+                &self.waypoint_symbol,
+                trade_symbol,
+                units,
+            )
+            .await
+        {
+            Result::Ok(r) => {
+                *self.supplied.entry(trade_symbol).or_default() += units;
+                info!(
+                    "[{context}] Supplied {units}x{} to {}",
+                    trade_symbol.to_string(),
+                    self.waypoint_symbol
+                );
+                Some(r.construction.is_complete)
+            }
+            Result::Err(e) => {
+                let required = self
+                    .outstanding(manager)
+                    .await
+                    .iter()
+                    .any(|&(s, _)| s == trade_symbol);
+                if required {
+                    info!(
+                        "[{context}] Failed to supply {units}x{}: {e}, retrying in 60 seconds",
+                        trade_symbol.to_string()
+                    );
+                    return None;
+                }
+
+                // Kept in the hold, the goods would leave no room to source the next material
+                info!(
+                    "[{context}] Failed to supply {units}x{}: {e}, no longer required so jettisoning them",
+                    trade_symbol.to_string()
+                );
+                client.jettison(ship_symbol, trade_symbol, units).await;
+                Some(false)
+            }
+        }
+    }
+}
+
+/// Makes room for the material by selling everything else in the hold, when docked at a
+/// market, and jettisoning what doesn't sell.
+async fn clear_hold(manager: &Manager, ship_symbol: &str, keep: TradeSymbol, sell: bool) {
+    let client = manager.client();
+    let cargo = client.get_ship_cargo(ship_symbol).await;
+
+    for item in cargo.inventory.iter().filter(|i| i.symbol != keep) {
+        if sell && client.sell(ship_symbol, item.symbol, item.units).await {
+            continue;
+        }
+
+        info!(
+            "[{ship_symbol}] Jettisoning {}x{} to make room for {}",
+            item.units,
+            item.symbol.to_string(),
+            keep.to_string()
+        );
+        client.jettison(ship_symbol, item.symbol, item.units).await;
+    }
+}
+
+/// Extracts once at the asteroid field the ship is at, jettisoning anything but the material.
+/// Returns what the ship holds once it has the units or its hold is full.
+async fn mine_once(
+    manager: &Manager,
+    ship_symbol: &str,
+    trade_symbol: TradeSymbol,
+    units: i32,
+) -> Extraction {
+    let context = ship_symbol;
+    let client = manager.client();
+
+    match client.extract_once(ship_symbol, None).await {
+        Result::Ok(r) => {
+            let yld = r.extraction.r#yield;
+            let cargo = if yld.symbol != trade_symbol {
+                client.jettison(ship_symbol, yld.symbol, yld.units).await;
+                // The extraction's cargo still counts what was jettisoned
+                client.get_ship_cargo(ship_symbol).await
+            } else {
+                r.cargo
+            };
+
+            let held = held_units(&cargo.inventory, trade_symbol);
+            info!(
+                "[{context}] Mined {}x{}, holding {held}/{units}x{}",
+                yld.units,
+                yld.symbol.to_string(),
+                trade_symbol.to_string()
+            );
+
+            if held >= units || cargo.capacity - cargo.units < 3 {
+                return Extraction::Holding(held);
+            }
+
+            Extraction::CoolingDown(r.cooldown.remaining_seconds as u64)
+        }
+        Result::Err(e) => match e.error.data {
+            ExtractResourceError::Cooldown { cooldown } => {
+                Extraction::CoolingDown(cooldown.remaining_seconds)
+            }
+            ExtractResourceError::Cargo { .. } | ExtractResourceError::Other(_) => {
+                let cargo = client.get_ship_cargo(ship_symbol).await;
+                Extraction::Holding(held_units(&cargo.inventory, trade_symbol))
+            }
+        },
+    }
+}
+
+/// Runs until the construction is complete, then frees the ship.
+#[async_trait]
+impl Behaviour for ConstructionProject {
+    fn name(&self) -> String {
+        "Construction".to_owned()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Supplying construction at {} ({:?})",
+            self.waypoint_symbol, self.state
+        )
+    }
+
+    fn save(&self) -> Option<SavedBehaviour> {
+        Some(SavedBehaviour::Construction(self.clone()))
+    }
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next {
+        self.supply_once(manager, ship_symbol).await
+    }
+}
+
+/// Materials still missing from the construction, largest shortfall first.
+pub fn outstanding_materials(construction: &Construction) -> Vec<(TradeSymbol, i32)> {
+    let mut outstanding: Vec<_> = construction
+        .materials
+        .iter()
+        .filter(|m| m.fulfilled < m.required)
+        .map(|m| (m.trade_symbol, m.required - m.fulfilled))
+        .collect();
+
+    outstanding.sort_by_key(|(_, units)| -units);
+    outstanding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spacedust::models::ConstructionMaterial;
+
+    #[test]
+    fn outstanding_materials_skips_fulfilled() {
+        let construction = Construction::new(
+            "X1-ZA40-I57".into(),
+            vec![
+                ConstructionMaterial::new(TradeSymbol::FabMats, 1600, 1600),
+                ConstructionMaterial::new(TradeSymbol::AdvancedCircuitry, 400, 100),
+                ConstructionMaterial::new(TradeSymbol::QuantumStabilizers, 1, 0),
+            ],
+            false,
+        );
+
+        assert_eq!(
+            outstanding_materials(&construction),
+            vec![
+                (TradeSymbol::AdvancedCircuitry, 300),
+                (TradeSymbol::QuantumStabilizers, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn resumes_saved_project_for_same_site() {
+        let mut project = ConstructionProject::new("X1-ZA40", "X1-ZA40-I57");
+        project.spent = 12000;
+        project.supplied.insert(TradeSymbol::FabMats, 40);
+        project.state = ConstructionState::Delivering {
+            trade_symbol: TradeSymbol::FabMats,
+            units: 40,
+        };
+
+        let json = serde_json::to_string(&SavedBehaviour::Construction(project.clone())).unwrap();
+        let saved = serde_json::from_str::<SavedBehaviour>(&json).unwrap();
+
+        assert_eq!(
+            ConstructionProject::resume(Some(saved.clone()), "X1-ZA40", "X1-ZA40-I57"),
+            project
+        );
+        assert_eq!(
+            ConstructionProject::resume(Some(saved), "X1-ZA40", "X1-ZA40-J58"),
+            ConstructionProject::new("X1-ZA40", "X1-ZA40-J58")
+        );
+    }
+}
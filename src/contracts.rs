@@ -0,0 +1,300 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use spacedust::models::{contract, Contract, ShipCargoItem, TradeSymbol};
+
+use crate::{accounts::Session, client::Client, markets::MarketIndex};
+
+/// Goods still to deliver for the contract, as (good, destination, units).
+pub fn remaining_deliveries(contract: &Contract) -> Vec<(TradeSymbol, String, i32)> {
+    contract
+        .terms
+        .deliver
+        .iter()
+        .flatten()
+        .filter(|d| d.units_fulfilled < d.units_required)
+        .filter_map(|d| {
+            let trade_symbol = serde_json::from_value(d.trade_symbol.clone().into()).ok()?;
+            Some((
+                trade_symbol,
+                d.destination_symbol.to_owned(),
+                d.units_required - d.units_fulfilled,
+            ))
+        })
+        .collect()
+}
+
+/// Goods bought for a contract, on their way to its destination.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub contract_id: String,
+    pub trade_symbol: TradeSymbol,
+    pub destination: String,
+    pub units: i32,
+}
+
+/// Where a contract's next goods come from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    /// Already in the hold.
+    Held(Delivery),
+    /// To buy at the market before delivering.
+    Market { market: String, delivery: Delivery },
+}
+
+pub fn held_units(inventory: &[ShipCargoItem], trade_symbol: TradeSymbol) -> i32 {
+    inventory
+        .iter()
+        .filter(|i| i.symbol == trade_symbol)
+        .map(|i| i.units)
+        .sum()
+}
+
+/// Accepts procurement contracts and delivers their goods, buying them from known markets.
+#[derive(Clone)]
+pub struct Contractor {
+    log_context: String,
+    client: Client,
+    markets: MarketIndex,
+}
+
+impl Contractor {
+    pub fn new(log_context: &str, session: &Session, markets: MarketIndex) -> Self {
+        Self {
+            log_context: log_context.to_owned(),
+            client: Client::new(log_context.to_owned(), session),
+            markets,
+        }
+    }
+
+    /// The contract we're working on, accepting a pending procurement contract if we have none.
+    pub async fn active_contract(&self) -> Option<Contract> {
+        let context = &self.log_context;
+        let contracts = self.client.get_contracts().await;
+
+        if let Some(active) = contracts.iter().find(|c| c.accepted && !c.fulfilled) {
+            return Some(active.clone());
+        }
+
+        let pending = contracts
+            .into_iter()
+            .find(|c| !c.accepted && c.r#type == contract::Type::Procurement)?;
+
+        match self.client.accept_contract(&pending.id).await {
+            Result::Ok(r) => {
+                info!(
+                    "[{context}] Accepted contract {} for {} + {} credits. Total credits={}",
+                    pending.id,
+                    pending.terms.payment.on_accepted,
+                    pending.terms.payment.on_fulfilled,
+                    r.agent.credits
+                );
+                Some(*r.contract)
+            }
+            Result::Err(e) => {
+                info!("[{context}] Failed to accept contract {}: {e}", pending.id);
+                None
+            }
+        }
+    }
+
+    /// Whether there is a contract to work on: an accepted one, or a procurement contract to
+    /// accept.
+    pub async fn available(&self) -> bool {
+        self.client.get_contracts().await.iter().any(|c| {
+            (c.accepted && !c.fulfilled) || (!c.accepted && c.r#type == contract::Type::Procurement)
+        })
+    }
+
+    /// Asks the faction for a new contract. The ship must be docked at a faction waypoint.
+    pub async fn negotiate(&self, ship_symbol: &str) {
+        let context = &self.log_context;
+
+        match self.client.negotiate_contract(ship_symbol).await {
+            Result::Ok(c) => info!("[{context}] Negotiated contract {}", c.id),
+            Result::Err(e) => info!("[{context}] Failed to negotiate contract: {e}"),
+        }
+    }
+
+    /// Where the contract's next goods come from: the hold if the ship already has some,
+    /// otherwise the known market selling them, buying as many as fit. Fulfills the contract
+    /// instead if everything is delivered. Returns None if there was nothing to do.
+    pub async fn source(&self, ship_symbol: &str, contract: &Contract) -> Option<Source> {
+        let context = &self.log_context;
+
+        let Some((trade_symbol, destination, units)) =
+            remaining_deliveries(contract).into_iter().next()
+        else {
+            self.fulfill(contract).await;
+            return None;
+        };
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let held = held_units(&ship.cargo.inventory, trade_symbol);
+        let delivery = Delivery {
+            contract_id: contract.id.to_owned(),
+            trade_symbol,
+            destination,
+            units: held.min(units),
+        };
+        if held > 0 {
+            return Some(Source::Held(delivery));
+        }
+
+        let Some(seller) = self
+            .markets
+            .sellers(&ship.nav.system_symbol, trade_symbol)
+            .into_iter()
+            .next()
+        else {
+            info!(
+                "[{context}] No known market sells {} for contract {}",
+                trade_symbol.to_string(),
+                contract.id
+            );
+            return None;
+        };
+
+        Some(Source::Market {
+            market: seller.market.symbol,
+            delivery: Delivery {
+                units: i32::min(units, ship.cargo.capacity - ship.cargo.units),
+                ..delivery
+            },
+        })
+    }
+
+    /// Buys the delivery's goods at the market the ship is at. Returns what to deliver, None if
+    /// nothing could be bought.
+    pub async fn buy(&self, ship_symbol: &str, delivery: &Delivery) -> Option<Delivery> {
+        let context = &self.log_context;
+        let trade_symbol = delivery.trade_symbol;
+
+        self.client.dock_ship(ship_symbol).await;
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let market = self
+            .client
+            .get_market(&ship.nav.system_symbol, &ship.nav.waypoint_symbol)
+            .await;
+        self.markets.record(&market);
+
+        let trade_volume = market
+            .trade_goods
+            .unwrap_or_default()
+            .iter()
+            .find(|g| g.symbol == trade_symbol)
+            .map_or(delivery.units, |g| g.trade_volume);
+
+        let mut remaining = delivery.units;
+        while remaining > 0 {
+            let batch = i32::min(remaining, trade_volume);
+            match self
+                .client
+                .purchase_cargo(ship_symbol, trade_symbol, batch)
+                .await
+            {
+                Result::Ok(r) => {
+                    remaining -= r.transaction.units;
+                    info!(
+                        "[{context}] Bought {}x{} for {} credits. Total credits={}",
+                        r.transaction.units,
+                        r.transaction.trade_symbol,
+                        r.transaction.total_price,
+                        r.agent.credits
+                    );
+                }
+                Result::Err(e) => {
+                    info!(
+                        "[{context}] Failed to buy {batch}x{}: {e}",
+                        trade_symbol.to_string()
+                    );
+                    break;
+                }
+            }
+        }
+
+        let cargo = self.client.get_ship_cargo(ship_symbol).await;
+        let held = held_units(&cargo.inventory, trade_symbol).min(delivery.units);
+        (held > 0).then(|| Delivery {
+            units: held,
+            ..delivery.clone()
+        })
+    }
+
+    /// Delivers the goods at the destination the ship is at, fulfilling the contract once
+    /// everything is delivered.
+    pub async fn deliver(&self, ship_symbol: &str, delivery: &Delivery) -> bool {
+        let context = &self.log_context;
+        let Delivery {
+            contract_id,
+            trade_symbol,
+            units,
+            ..
+        } = delivery;
+
+        self.client.dock_ship(ship_symbol).await;
+
+        match self
+            .client
+            .deliver_contract(contract_id, ship_symbol, *trade_symbol, *units)
+            .await
+        {
+            Result::Ok(r) => {
+                info!(
+                    "[{context}] Delivered {units}x{} for contract {contract_id}",
+                    trade_symbol.to_string()
+                );
+                if remaining_deliveries(&r.contract).is_empty() {
+                    self.fulfill(&r.contract).await;
+                }
+                true
+            }
+            Result::Err(e) => {
+                info!(
+                    "[{context}] Failed to deliver {units}x{}: {e}",
+                    trade_symbol.to_string()
+                );
+                false
+            }
+        }
+    }
+
+    async fn fulfill(&self, contract: &Contract) -> bool {
+        let context = &self.log_context;
+
+        match self.client.fulfill_contract(&contract.id).await {
+            Result::Ok(r) => {
+                info!(
+                    "[{context}] Fulfilled contract {}. Total credits={}",
+                    contract.id, r.agent.credits
+                );
+                true
+            }
+            Result::Err(e) => {
+                info!(
+                    "[{context}] Failed to fulfill contract {}: {e}",
+                    contract.id
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining_deliveries_skips_delivered_goods() {
+        let str = "{\"id\":\"clr1\",\"factionSymbol\":\"COSMIC\",\"type\":\"PROCUREMENT\",\"terms\":{\"deadline\":\"2024-01-20T00:00:00.000Z\",\"payment\":{\"onAccepted\":1000,\"onFulfilled\":5000},\"deliver\":[{\"tradeSymbol\":\"IRON_ORE\",\"destinationSymbol\":\"X1-ZA40-A1\",\"unitsRequired\":50,\"unitsFulfilled\":20},{\"tradeSymbol\":\"COPPER_ORE\",\"destinationSymbol\":\"X1-ZA40-A1\",\"unitsRequired\":10,\"unitsFulfilled\":10}]},\"accepted\":true,\"fulfilled\":false,\"expiration\":\"2024-01-20T00:00:00.000Z\"}";
+
+        let contract: Contract = serde_json::from_str(str).unwrap();
+
+        assert_eq!(
+            remaining_deliveries(&contract),
+            vec![(TradeSymbol::IronOre, "X1-ZA40-A1".to_owned(), 30)]
+        );
+    }
+}
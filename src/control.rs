@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    behaviours::Order,
+    fleet::FleetCommand,
+    roles::Role,
+    scheduler::{SchedulerHandle, ShipStatus},
+    strategy::{Strategy, StrategySettings, StrategyUpdate},
+};
+
+type Response<T> = Result<Json<T>, (StatusCode, String)>;
+
+/// How each running agent can be reached.
+#[derive(Clone)]
+struct AgentControl {
+    scheduler: SchedulerHandle,
+    strategy: Strategy,
+    fleet: mpsc::Sender<FleetCommand>,
+}
+
+/// A ship's place in the scheduler, as the control API shows it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShipView {
+    ship_symbol: String,
+    behaviour: Option<String>,
+    queued: usize,
+    running: bool,
+    paused: bool,
+    wake_at: Option<String>,
+    wake_reason: Option<String>,
+}
+
+impl ShipView {
+    fn new(status: ShipStatus) -> Self {
+        Self {
+            ship_symbol: status.ship_symbol,
+            behaviour: status.behaviour,
+            queued: status.queued,
+            running: status.running,
+            paused: status.paused,
+            wake_at: status.wake.map(|(at, _)| at.to_rfc3339()),
+            wake_reason: status.wake.map(|(_, reason)| format!("{reason:?}")),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleChange {
+    role: Role,
+}
+
+/// Local HTTP API to steer the agents while they play: pausing and resuming ships, changing
+/// their role, giving them one-off orders and tuning the strategy.
+#[derive(Clone, Default)]
+pub struct Control {
+    agents: Arc<Mutex<HashMap<String, AgentControl>>>,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the agent controllable. Returns the commands for its fleet controller.
+    pub fn register(
+        &self,
+        agent: &str,
+        scheduler: SchedulerHandle,
+        strategy: Strategy,
+    ) -> mpsc::Receiver<FleetCommand> {
+        let (fleet, commands) = mpsc::channel(8);
+        self.agents.lock().unwrap().insert(
+            agent.to_owned(),
+            AgentControl {
+                scheduler,
+                strategy,
+                fleet,
+            },
+        );
+        commands
+    }
+
+    fn agent(&self, agent: &str) -> Result<AgentControl, (StatusCode, String)> {
+        self.agents
+            .lock()
+            .unwrap()
+            .get(agent)
+            .cloned()
+            .ok_or((StatusCode::NOT_FOUND, format!("No agent {agent} running")))
+    }
+
+    pub fn routes(&self) -> Router {
+        Router::new()
+            .route("/api/agents/:agent/ships", get(ships))
+            .route("/api/agents/:agent/ships/:ship/pause", post(pause))
+            .route("/api/agents/:agent/ships/:ship/resume", post(resume))
+            .route("/api/agents/:agent/ships/:ship/role", put(change_role))
+            .route("/api/agents/:agent/ships/:ship/orders", post(order))
+            .route(
+                "/api/agents/:agent/strategy",
+                get(strategy).patch(update_strategy),
+            )
+            .with_state(self.clone())
+    }
+}
+
+async fn ships(
+    State(control): State<Control>,
+    Path(agent): Path<String>,
+) -> Response<Vec<ShipView>> {
+    let statuses = control.agent(&agent)?.scheduler.inspect().await;
+    Ok(Json(statuses.into_iter().map(ShipView::new).collect()))
+}
+
+async fn pause(
+    State(control): State<Control>,
+    Path((agent, ship)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    control.agent(&agent)?.scheduler.pause(&ship);
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn resume(
+    State(control): State<Control>,
+    Path((agent, ship)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    control.agent(&agent)?.scheduler.resume(&ship);
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Hands the command to the agent's fleet controller, which carries it out between its other
+/// work.
+async fn send(
+    agent: AgentControl,
+    command: FleetCommand,
+) -> Result<StatusCode, (StatusCode, String)> {
+    agent.fleet.send(command).await.map_err(|_| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The fleet controller stopped".to_owned(),
+        )
+    })?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn change_role(
+    State(control): State<Control>,
+    Path((agent, ship)): Path<(String, String)>,
+    Json(change): Json<RoleChange>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send(control.agent(&agent)?, FleetCommand::Pin(ship, change.role)).await
+}
+
+async fn order(
+    State(control): State<Control>,
+    Path((agent, ship)): Path<(String, String)>,
+    Json(order): Json<Order>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    send(control.agent(&agent)?, FleetCommand::Order(ship, order)).await
+}
+
+async fn strategy(
+    State(control): State<Control>,
+    Path(agent): Path<String>,
+) -> Response<StrategySettings> {
+    Ok(Json(control.agent(&agent)?.strategy.settings()))
+}
+
+async fn update_strategy(
+    State(control): State<Control>,
+    Path(agent): Path<String>,
+    Json(update): Json<StrategyUpdate>,
+) -> Response<StrategySettings> {
+    Ok(Json(control.agent(&agent)?.strategy.update(update)))
+}
+
+#[cfg(test)]
+mod tests {
+    use spacedust::models::TradeSymbol;
+
+    use super::*;
+
+    #[test]
+    fn orders_are_read_from_json() {
+        let order: Order =
+            serde_json::from_str("{\"order\":\"navigate\",\"waypointSymbol\":\"X1-ZA40-B7\"}")
+                .unwrap();
+        assert_eq!(
+            order,
+            Order::Navigate {
+                waypoint_symbol: "X1-ZA40-B7".to_owned()
+            }
+        );
+
+        let order: Order =
+            serde_json::from_str("{\"order\":\"sell\",\"tradeSymbol\":\"IRON_ORE\",\"units\":10}")
+                .unwrap();
+        assert_eq!(
+            order,
+            Order::Sell {
+                trade_symbol: Some(TradeSymbol::IronOre),
+                units: Some(10)
+            }
+        );
+    }
+}
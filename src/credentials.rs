@@ -0,0 +1,80 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+/// The identity of an agent we registered, kept so its token isn't lost.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Credentials {
+    pub token: String,
+    pub agent_symbol: String,
+    pub faction: String,
+    pub headquarters: String,
+    pub reset_date: String,
+}
+
+impl Credentials {
+    pub fn load(path: &Path) -> Option<Self> {
+        let json = fs::read_to_string(path).ok()?;
+
+        serde_json::from_str(&json)
+            .inspect_err(|e| info!("[CREDENTIALS] Ignoring unreadable {}: {e}", path.display()))
+            .ok()
+    }
+
+    /// Writes the credentials readable by their owner only, through a temporary file so a crash
+    /// mid-write doesn't lose the previous ones.
+    pub fn save(&self, path: &Path) {
+        let temporary = path.with_extension("tmp");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temporary)
+            .unwrap();
+
+        file.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())
+            .unwrap();
+        fs::rename(&temporary, path).unwrap();
+
+        info!(
+            "[CREDENTIALS] Saved {} credentials to {}",
+            self.agent_symbol,
+            path.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn saved_credentials_are_private() {
+        let path = std::env::temp_dir().join(format!("credentials-{}.json", std::process::id()));
+        let credentials = Credentials {
+            token: "ey.token".into(),
+            agent_symbol: "MXZ".into(),
+            faction: "COSMIC".into(),
+            headquarters: "X1-ZA40-A1".into(),
+            reset_date: "2024-01-07".into(),
+        };
+
+        credentials.save(&path);
+
+        assert_eq!(Credentials::load(&path), Some(credentials));
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::State,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html,
+    },
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream, StreamExt};
+use serde::Serialize;
+use spacedust::models::{Contract, Ship, ShipNavRoute, ShipNavStatus, Waypoint};
+use tokio::sync::broadcast;
+
+use crate::{client::parse_time, roles::Role, scheduler::ShipStatus};
+
+/// Credit readings kept per agent for the chart.
+const CREDIT_HISTORY: usize = 1440;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShipView {
+    pub symbol: String,
+    pub role: Option<Role>,
+    pub waypoint: String,
+    pub nav_status: String,
+    /// Where the ship is on the system map, between its origin and destination while in transit.
+    pub x: f64,
+    pub y: f64,
+    pub cargo_units: i32,
+    pub cargo_capacity: i32,
+    pub cargo: Vec<(String, i32)>,
+    pub cooldown_until: Option<String>,
+    pub task: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WaypointView {
+    pub symbol: String,
+    pub r#type: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractView {
+    pub id: String,
+    pub deadline: String,
+    /// Deliveries as (good, destination, units fulfilled, units required).
+    pub deliveries: Vec<(String, String, i32, i32)>,
+}
+
+/// Everything the dashboard shows of one agent.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub agent: String,
+    pub credits: i64,
+    /// Credits over time, as (RFC 3339 time, credits).
+    pub credit_history: Vec<(String, i64)>,
+    pub ships: Vec<ShipView>,
+    pub contracts: Vec<ContractView>,
+    pub system: String,
+    pub waypoints: Vec<WaypointView>,
+}
+
+/// Where a ship on the route is at `now`, interpolating along it while in transit.
+fn position(status: ShipNavStatus, route: &ShipNavRoute, now: DateTime<Utc>) -> (f64, f64) {
+    let (to_x, to_y) = (route.destination.x as f64, route.destination.y as f64);
+    if status != ShipNavStatus::InTransit {
+        return (to_x, to_y);
+    }
+
+    let departure = parse_time(&route.departure_time);
+    let arrival = parse_time(&route.arrival);
+    let total = (arrival - departure).num_seconds().max(1) as f64;
+    let progress = ((now - departure).num_seconds() as f64 / total).clamp(0.0, 1.0);
+
+    let (from_x, from_y) = (route.origin.x as f64, route.origin.y as f64);
+    (
+        from_x + (to_x - from_x) * progress,
+        from_y + (to_y - from_y) * progress,
+    )
+}
+
+impl ShipView {
+    pub fn new(ship: &Ship, role: Option<Role>, status: Option<&ShipStatus>) -> Self {
+        let (x, y) = position(ship.nav.status, &ship.nav.route, Utc::now());
+
+        Self {
+            symbol: ship.symbol.to_owned(),
+            role,
+            waypoint: ship.nav.waypoint_symbol.to_owned(),
+            nav_status: format!("{:?}", ship.nav.status),
+            x,
+            y,
+            cargo_units: ship.cargo.units,
+            cargo_capacity: ship.cargo.capacity,
+            cargo: ship
+                .cargo
+                .inventory
+                .iter()
+                .map(|i| (i.symbol.to_string(), i.units))
+                .collect(),
+            cooldown_until: ship
+                .cooldown
+                .expiration
+                .clone()
+                .filter(|_| ship.cooldown.remaining_seconds > 0),
+            task: status.and_then(|s| s.behaviour.clone()),
+        }
+    }
+}
+
+impl WaypointView {
+    pub fn new(waypoint: &Waypoint) -> Self {
+        Self {
+            symbol: waypoint.symbol.to_owned(),
+            r#type: format!("{:?}", waypoint.r#type),
+            x: waypoint.x,
+            y: waypoint.y,
+        }
+    }
+}
+
+impl ContractView {
+    /// The contract's view, None once it is fulfilled or if it wasn't accepted.
+    pub fn new(contract: &Contract) -> Option<Self> {
+        if !contract.accepted || contract.fulfilled {
+            return None;
+        }
+
+        Some(Self {
+            id: contract.id.to_owned(),
+            deadline: contract.terms.deadline.to_owned(),
+            deliveries: contract
+                .terms
+                .deliver
+                .iter()
+                .flatten()
+                .map(|d| {
+                    (
+                        d.trade_symbol.to_owned(),
+                        d.destination_symbol.to_owned(),
+                        d.units_fulfilled,
+                        d.units_required,
+                    )
+                })
+                .collect(),
+        })
+    }
+}
+
+/// The latest snapshot of every agent, pushed to the browsers watching as server-sent events.
+#[derive(Clone)]
+pub struct Dashboard {
+    snapshots: Arc<Mutex<HashMap<String, Snapshot>>>,
+    events: broadcast::Sender<String>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self {
+            snapshots: Arc::default(),
+            events: broadcast::channel(16).0,
+        }
+    }
+
+    /// Sends the snapshot to every browser watching, adding its credits to the agent's history.
+    pub fn publish(&self, mut snapshot: Snapshot) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+
+        let mut history = snapshots
+            .remove(&snapshot.agent)
+            .map(|s| s.credit_history)
+            .unwrap_or_default();
+        history.push((Utc::now().to_rfc3339(), snapshot.credits));
+        if history.len() > CREDIT_HISTORY {
+            history.remove(0);
+        }
+        snapshot.credit_history = history;
+
+        // Nobody watching is fine, they get the latest snapshots when they connect
+        let _ = self.events.send(serde_json::to_string(&snapshot).unwrap());
+        snapshots.insert(snapshot.agent.to_owned(), snapshot);
+    }
+
+    pub fn routes(&self) -> Router {
+        Router::new()
+            .route("/", get(|| async { Html(include_str!("dashboard.html")) }))
+            .route("/events", get(events))
+            .with_state(self.clone())
+    }
+}
+
+/// The latest snapshot of every agent, then each one published.
+async fn events(
+    State(dashboard): State<Dashboard>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = dashboard.events.subscribe();
+    let latest: Vec<_> = dashboard
+        .snapshots
+        .lock()
+        .unwrap()
+        .values()
+        .map(|s| serde_json::to_string(s).unwrap())
+        .collect();
+
+    let published = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Result::Ok(json) => return Some((json, receiver)),
+                Result::Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Result::Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(latest)
+        .chain(published)
+        .map(|json| Ok(Event::default().data(json)));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ships_in_transit_are_placed_along_their_route() {
+        let route: ShipNavRoute = serde_json::from_str("{\"origin\":{\"symbol\":\"X1-ZA40-A1\",\"type\":\"PLANET\",\"systemSymbol\":\"X1-ZA40\",\"x\":0,\"y\":0},\"destination\":{\"symbol\":\"X1-ZA40-B7\",\"type\":\"ASTEROID_FIELD\",\"systemSymbol\":\"X1-ZA40\",\"x\":100,\"y\":-50},\"departureTime\":\"2024-01-20T00:00:00Z\",\"arrival\":\"2024-01-20T00:01:40Z\"}").unwrap();
+
+        let at = |status, time: &str| position(status, &route, parse_time(time));
+        assert_eq!(
+            at(ShipNavStatus::InTransit, "2024-01-20T00:00:25Z"),
+            (25.0, -12.5)
+        );
+        assert_eq!(
+            at(ShipNavStatus::InTransit, "2024-01-20T00:05:00Z"),
+            (100.0, -50.0)
+        );
+        assert_eq!(
+            at(ShipNavStatus::InOrbit, "2024-01-20T00:00:25Z"),
+            (100.0, -50.0)
+        );
+    }
+
+    #[test]
+    fn publishing_keeps_the_credit_history() {
+        let dashboard = Dashboard::new();
+        let snapshot = |credits| Snapshot {
+            agent: "MXZ".to_owned(),
+            credits,
+            credit_history: vec![],
+            ships: vec![],
+            contracts: vec![],
+            system: "X1-ZA40".to_owned(),
+            waypoints: vec![],
+        };
+
+        dashboard.publish(snapshot(100));
+        dashboard.publish(snapshot(250));
+
+        let history = dashboard.snapshots.lock().unwrap()["MXZ"]
+            .credit_history
+            .iter()
+            .map(|(_, c)| *c)
+            .collect::<Vec<_>>();
+        assert_eq!(history, vec![100, 250]);
+    }
+}
@@ -0,0 +1,717 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use spacedust::models::{
+    Contract, JumpGate, Market, MarketTransaction, Ship, Shipyard, ShipyardTransaction, Survey,
+    Waypoint,
+};
+
+use crate::{ledger::LedgerEntry, roles::Role};
+
+/// Schema migrations, applied in order. The database's `user_version` is the number applied so
+/// far, so only append to this list.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE systems (
+        symbol TEXT PRIMARY KEY,
+        waypoints_fetched_at TEXT NOT NULL
+    );
+    CREATE TABLE waypoints (
+        symbol TEXT PRIMARY KEY,
+        system_symbol TEXT NOT NULL,
+        type TEXT NOT NULL,
+        x INTEGER NOT NULL,
+        y INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX waypoints_system ON waypoints (system_symbol);
+    CREATE TABLE waypoint_traits (
+        waypoint_symbol TEXT NOT NULL REFERENCES waypoints (symbol) ON DELETE CASCADE,
+        trait TEXT NOT NULL,
+        PRIMARY KEY (waypoint_symbol, trait)
+    );
+    CREATE TABLE markets (
+        symbol TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        prices_updated_at TEXT
+    );
+    CREATE TABLE shipyards (
+        symbol TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        prices_updated_at TEXT
+    );
+    CREATE TABLE jump_gates (
+        waypoint_symbol TEXT NOT NULL,
+        connection TEXT NOT NULL,
+        PRIMARY KEY (waypoint_symbol, connection)
+    );
+    CREATE TABLE surveys (
+        signature TEXT PRIMARY KEY,
+        waypoint_symbol TEXT NOT NULL,
+        expiration TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE ships (
+        symbol TEXT PRIMARY KEY,
+        data TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE transactions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp TEXT NOT NULL,
+        ship_symbol TEXT NOT NULL,
+        waypoint_symbol TEXT NOT NULL,
+        type TEXT NOT NULL,
+        trade_symbol TEXT NOT NULL,
+        units INTEGER NOT NULL,
+        price_per_unit INTEGER NOT NULL,
+        total_price INTEGER NOT NULL
+    );
+    CREATE TABLE contracts (
+        id TEXT PRIMARY KEY,
+        type TEXT NOT NULL,
+        accepted INTEGER NOT NULL,
+        fulfilled INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    "CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );",
+    "CREATE TABLE ledger (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp TEXT NOT NULL,
+        ship_symbol TEXT,
+        role TEXT,
+        kind TEXT NOT NULL,
+        amount INTEGER NOT NULL
+    );
+    CREATE TABLE contract_deliveries (
+        contract_id TEXT NOT NULL,
+        ship_symbol TEXT NOT NULL,
+        role TEXT,
+        units INTEGER NOT NULL
+    );
+    CREATE INDEX contract_deliveries_contract ON contract_deliveries (contract_id);",
+    // Systems saved before their waypoints were paginated only have the first page of them
+    "DELETE FROM systems;",
+];
+
+/// What the server reset wipes, along with the ledger of the ships it takes with it. Our
+/// transactions are kept as a record of past runs.
+const UNIVERSE_TABLES: &[&str] = &[
+    "waypoint_traits",
+    "waypoints",
+    "systems",
+    "markets",
+    "shipyards",
+    "jump_gates",
+    "surveys",
+    "ships",
+    "contracts",
+    "ledger",
+    "contract_deliveries",
+];
+
+/// Embedded SQLite store of what we know about the universe and our fleet, so it survives
+/// restarts and doesn't have to be fetched again.
+#[derive(Clone)]
+pub struct Database {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Database {
+    pub fn open(path: &str) -> Self {
+        Self::with_connection(Connection::open(path).unwrap())
+    }
+
+    fn with_connection(mut connection: Connection) -> Self {
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .unwrap();
+        migrate(&mut connection);
+
+        Self {
+            connection: Arc::new(Mutex::new(connection)),
+        }
+    }
+
+    /// Writes a copy of the whole database to `path`, to keep a finished run's data around.
+    /// Replaces an earlier copy, like one left by an attempt to start over that failed.
+    pub fn archive(&self, path: &Path) -> rusqlite::Result<()> {
+        // VACUUM INTO refuses to write over an existing file
+        fs::remove_file(path).ok();
+
+        self.connection
+            .lock()
+            .unwrap()
+            .execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .map(|_| ())
+    }
+
+    /// Moves everything written to the write-ahead log into the database file, so it is complete
+    /// on its own once we exit.
+    pub fn checkpoint(&self) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+            .unwrap();
+    }
+
+    /// Clears the universe if the server reset since the last reset date we saw. Returns whether
+    /// it did.
+    pub fn observe_reset(&self, reset_date: &str) -> bool {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().unwrap();
+
+        let last: Option<String> = transaction
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'reset_date'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap();
+        let reset = last.as_deref().is_some_and(|l| l != reset_date);
+
+        if reset {
+            info!("[DATABASE] Server reset on {reset_date}, clearing the saved universe");
+            for table in UNIVERSE_TABLES {
+                transaction
+                    .execute(&format!("DELETE FROM {table}"), [])
+                    .unwrap();
+            }
+        }
+
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('reset_date', ?1)",
+                params![reset_date],
+            )
+            .unwrap();
+        transaction.commit().unwrap();
+        reset
+    }
+
+    /// The system's waypoints, None if we never fetched them.
+    pub fn waypoints(&self, system_symbol: &str) -> Option<Vec<Waypoint>> {
+        let connection = self.connection.lock().unwrap();
+
+        connection
+            .query_row(
+                "SELECT 1 FROM systems WHERE symbol = ?1",
+                params![system_symbol],
+                |_| Ok(()),
+            )
+            .optional()
+            .unwrap()?;
+
+        let mut statement = connection
+            .prepare("SELECT data FROM waypoints WHERE system_symbol = ?1 ORDER BY symbol")
+            .unwrap();
+        let waypoints = statement
+            .query_map(params![system_symbol], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|data| from_json(&data.unwrap()))
+            .collect();
+
+        Some(waypoints)
+    }
+
+    pub fn save_waypoints(&self, system_symbol: &str, waypoints: &[Waypoint]) {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().unwrap();
+
+        for waypoint in waypoints {
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO waypoints (symbol, system_symbol, type, x, y, data)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        waypoint.symbol,
+                        system_symbol,
+                        waypoint.r#type.to_string(),
+                        waypoint.x,
+                        waypoint.y,
+                        to_json(waypoint)
+                    ],
+                )
+                .unwrap();
+
+            transaction
+                .execute(
+                    "DELETE FROM waypoint_traits WHERE waypoint_symbol = ?1",
+                    params![waypoint.symbol],
+                )
+                .unwrap();
+            for waypoint_trait in &waypoint.traits {
+                transaction
+                    .execute(
+                        "INSERT INTO waypoint_traits (waypoint_symbol, trait) VALUES (?1, ?2)",
+                        params![waypoint.symbol, waypoint_trait.symbol.to_string()],
+                    )
+                    .unwrap();
+            }
+        }
+
+        transaction
+            .execute(
+                "INSERT OR REPLACE INTO systems (symbol, waypoints_fetched_at) VALUES (?1, ?2)",
+                params![system_symbol, Utc::now().to_rfc3339()],
+            )
+            .unwrap();
+        transaction.commit().unwrap();
+    }
+
+    pub fn save_jump_gate(&self, jump_gate: &JumpGate) {
+        let connection = self.connection.lock().unwrap();
+
+        for connected in &jump_gate.connections {
+            connection
+                .execute(
+                    "INSERT OR IGNORE INTO jump_gates (waypoint_symbol, connection) VALUES (?1, ?2)",
+                    params![jump_gate.symbol, connected],
+                )
+                .unwrap();
+        }
+    }
+
+    /// Every market we've seen, with when we last saw its prices.
+    pub fn markets(&self) -> Vec<(Market, Option<DateTime<Utc>>)> {
+        self.listings("markets")
+    }
+
+    pub fn save_market(&self, market: &Market, prices_updated_at: Option<DateTime<Utc>>) {
+        self.save_listing("markets", &market.symbol, market, prices_updated_at);
+    }
+
+    /// Every shipyard we've seen, with when we last saw its prices.
+    pub fn shipyards(&self) -> Vec<(Shipyard, Option<DateTime<Utc>>)> {
+        self.listings("shipyards")
+    }
+
+    pub fn save_shipyard(&self, shipyard: &Shipyard, prices_updated_at: Option<DateTime<Utc>>) {
+        self.save_listing("shipyards", &shipyard.symbol, shipyard, prices_updated_at);
+    }
+
+    fn listings<T: DeserializeOwned>(&self, table: &str) -> Vec<(T, Option<DateTime<Utc>>)> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(&format!("SELECT data, prices_updated_at FROM {table}"))
+            .unwrap();
+
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .unwrap();
+
+        rows.map(|row| {
+            let (data, updated) = row.unwrap();
+            (from_json(&data), updated.map(|u| parse_time(&u)))
+        })
+        .collect()
+    }
+
+    fn save_listing<T: Serialize>(
+        &self,
+        table: &str,
+        symbol: &str,
+        listing: &T,
+        prices_updated_at: Option<DateTime<Utc>>,
+    ) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO {table} (symbol, data, prices_updated_at)
+                     VALUES (?1, ?2, ?3)"
+                ),
+                params![
+                    symbol,
+                    to_json(listing),
+                    prices_updated_at.map(|u| u.to_rfc3339())
+                ],
+            )
+            .unwrap();
+    }
+
+    /// Surveys that haven't expired yet.
+    pub fn surveys(&self) -> Vec<Survey> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .execute(
+                "DELETE FROM surveys WHERE expiration < ?1",
+                params![Utc::now().to_rfc3339()],
+            )
+            .unwrap();
+
+        let mut statement = connection.prepare("SELECT data FROM surveys").unwrap();
+        let surveys = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|data| from_json(&data.unwrap()))
+            .collect();
+        surveys
+    }
+
+    pub fn save_survey(&self, survey: &Survey) {
+        let expiration = parse_time(&survey.expiration).to_rfc3339();
+
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO surveys (signature, waypoint_symbol, expiration, data)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![survey.signature, survey.symbol, expiration, to_json(survey)],
+            )
+            .unwrap();
+    }
+
+    pub fn remove_survey(&self, signature: &str) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "DELETE FROM surveys WHERE signature = ?1",
+                params![signature],
+            )
+            .unwrap();
+    }
+
+    pub fn save_ship(&self, ship: &Ship) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO ships (symbol, data, updated_at) VALUES (?1, ?2, ?3)",
+                params![ship.symbol, to_json(ship), Utc::now().to_rfc3339()],
+            )
+            .unwrap();
+    }
+
+    pub fn save_contract(&self, contract: &Contract) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO contracts (id, type, accepted, fulfilled, data, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    contract.id,
+                    to_text(&contract.r#type),
+                    contract.accepted,
+                    contract.fulfilled,
+                    to_json(contract),
+                    Utc::now().to_rfc3339()
+                ],
+            )
+            .unwrap();
+    }
+
+    pub fn record_market_transaction(&self, transaction: &MarketTransaction) {
+        self.record_transaction(
+            &transaction.timestamp,
+            &transaction.ship_symbol,
+            &transaction.waypoint_symbol,
+            &to_text(&transaction.r#type),
+            &transaction.trade_symbol,
+            transaction.units,
+            transaction.price_per_unit,
+            transaction.total_price,
+        );
+    }
+
+    pub fn record_ship_purchase(&self, transaction: &ShipyardTransaction) {
+        self.record_transaction(
+            &transaction.timestamp,
+            &transaction.ship_symbol,
+            &transaction.waypoint_symbol,
+            "SHIP_PURCHASE",
+            &transaction.ship_type,
+            1,
+            transaction.price,
+            transaction.price,
+        );
+    }
+
+    pub fn record_ledger_entry(&self, entry: &LedgerEntry) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO ledger (timestamp, ship_symbol, role, kind, amount)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    entry.time.to_rfc3339(),
+                    entry.ship_symbol,
+                    entry.role.map(|r| to_text(&r)),
+                    to_text(&entry.kind),
+                    entry.amount
+                ],
+            )
+            .unwrap();
+    }
+
+    /// Every credit delta recorded since the last reset, oldest first.
+    pub fn ledger(&self) -> Vec<LedgerEntry> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT timestamp, ship_symbol, role, kind, amount FROM ledger ORDER BY id")
+            .unwrap();
+
+        statement
+            .query_map([], |row| {
+                Ok(LedgerEntry {
+                    time: parse_time(&row.get::<_, String>(0)?),
+                    ship_symbol: row.get(1)?,
+                    role: row.get::<_, Option<String>>(2)?.map(|r| from_text(&r)),
+                    kind: from_text(&row.get::<_, String>(3)?),
+                    amount: row.get(4)?,
+                })
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    pub fn record_contract_delivery(
+        &self,
+        contract_id: &str,
+        ship_symbol: &str,
+        role: Option<Role>,
+        units: i32,
+    ) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO contract_deliveries (contract_id, ship_symbol, role, units)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![contract_id, ship_symbol, role.map(|r| to_text(&r)), units],
+            )
+            .unwrap();
+    }
+
+    /// The ships that delivered to the contract, with their role and the units delivered.
+    pub fn contract_deliveries(&self, contract_id: &str) -> Vec<(String, Option<Role>, i64)> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT ship_symbol, role, SUM(units) FROM contract_deliveries
+                 WHERE contract_id = ?1 GROUP BY ship_symbol, role ORDER BY ship_symbol",
+            )
+            .unwrap();
+
+        statement
+            .query_map(params![contract_id], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get::<_, Option<String>>(1)?.map(|r| from_text(&r)),
+                    row.get(2)?,
+                ))
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record_transaction(
+        &self,
+        timestamp: &str,
+        ship_symbol: &str,
+        waypoint_symbol: &str,
+        kind: &str,
+        trade_symbol: &str,
+        units: i32,
+        price_per_unit: i32,
+        total_price: i32,
+    ) {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO transactions
+                 (timestamp, ship_symbol, waypoint_symbol, type, trade_symbol, units, price_per_unit, total_price)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    timestamp,
+                    ship_symbol,
+                    waypoint_symbol,
+                    kind,
+                    trade_symbol,
+                    units,
+                    price_per_unit,
+                    total_price
+                ],
+            )
+            .unwrap();
+    }
+}
+
+/// The instant a timestamp read back from the database corresponds to on the monotonic clock.
+pub fn instant_at(time: DateTime<Utc>) -> Instant {
+    let age = (Utc::now() - time).to_std().unwrap_or_default();
+    Instant::now().checked_sub(age).unwrap_or_else(Instant::now)
+}
+
+/// The wall-clock time of an instant, to be saved in the database.
+pub fn datetime_at(instant: Instant) -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::from_std(instant.elapsed()).unwrap_or(chrono::Duration::zero())
+}
+
+fn migrate(connection: &mut Connection) {
+    let applied: usize = connection
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        info!("[DATABASE] Applying migration {}", version + 1);
+
+        let transaction = connection.transaction().unwrap();
+        transaction.execute_batch(migration).unwrap();
+        transaction
+            .pragma_update(None, "user_version", version + 1)
+            .unwrap();
+        transaction.commit().unwrap();
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+/// The serialized name of an enum variant, like `SELL` for a market sale.
+fn to_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value).unwrap() {
+        serde_json::Value::String(text) => text,
+        other => other.to_string(),
+    }
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> T {
+    serde_json::from_str(data).unwrap()
+}
+
+/// The enum variant serialized as `text` by [`to_text`].
+fn from_text<T: DeserializeOwned>(text: &str) -> T {
+    serde_json::from_value(serde_json::Value::String(text.to_owned())).unwrap()
+}
+
+fn parse_time(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time)
+        .unwrap()
+        .with_timezone(&Utc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Database {
+        Database::with_connection(Connection::open_in_memory().unwrap())
+    }
+
+    #[test]
+    fn migrations_are_applied_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection);
+        migrate(&mut connection);
+
+        let version: usize = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn waypoints_round_trip() {
+        let database = database();
+        assert_eq!(database.waypoints("X1-ZA40"), None);
+
+        let waypoint: Waypoint = serde_json::from_str("{\"symbol\":\"X1-ZA40-B7\",\"type\":\"ASTEROID_FIELD\",\"systemSymbol\":\"X1-ZA40\",\"x\":10,\"y\":-4,\"orbitals\":[],\"traits\":[{\"symbol\":\"MARKETPLACE\",\"name\":\"Marketplace\",\"description\":\"\"}],\"isUnderConstruction\":false}").unwrap();
+        database.save_waypoints("X1-ZA40", std::slice::from_ref(&waypoint));
+        database.save_waypoints("X1-ZA40", std::slice::from_ref(&waypoint));
+
+        assert_eq!(database.waypoints("X1-ZA40"), Some(vec![waypoint]));
+        assert_eq!(database.waypoints("X1-OTHER"), None);
+    }
+
+    #[test]
+    fn reset_clears_the_universe() {
+        let database = database();
+        database.save_waypoints("X1-ZA40", &[]);
+
+        assert!(!database.observe_reset("2024-01-07"));
+        assert_eq!(database.waypoints("X1-ZA40"), Some(vec![]));
+
+        assert!(database.observe_reset("2024-01-21"));
+        assert_eq!(database.waypoints("X1-ZA40"), None);
+    }
+
+    #[test]
+    fn archives_over_an_earlier_archive() {
+        let database = database();
+        database.save_waypoints("X1-ZA40", &[]);
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("universe.db");
+
+        database.archive(&path).unwrap();
+        database.archive(&path).unwrap();
+
+        let archived = Database::with_connection(Connection::open(&path).unwrap());
+        assert_eq!(archived.waypoints("X1-ZA40"), Some(vec![]));
+    }
+
+    #[test]
+    fn records_transactions() {
+        let database = database();
+        let transaction: MarketTransaction = serde_json::from_str("{\"waypointSymbol\":\"X1-ZA40-B7\",\"shipSymbol\":\"MXZ-1\",\"tradeSymbol\":\"IRON_ORE\",\"type\":\"SELL\",\"units\":10,\"pricePerUnit\":20,\"totalPrice\":200,\"timestamp\":\"2024-01-20T00:00:00.000Z\"}").unwrap();
+        database.record_market_transaction(&transaction);
+
+        let total: i64 = database
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT SUM(total_price) FROM transactions WHERE type = 'SELL'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(total, 200);
+    }
+
+    #[test]
+    fn ledger_round_trip() {
+        let database = database();
+        let entry = LedgerEntry {
+            time: parse_time("2024-01-20T00:00:00Z"),
+            ship_symbol: Some("MXZ-1".to_owned()),
+            role: Some(Role::Miner),
+            kind: crate::ledger::EntryKind::Trade,
+            amount: 450,
+        };
+        database.record_ledger_entry(&entry);
+        database.record_contract_delivery("c1", "MXZ-2", Some(Role::Hauler), 10);
+        database.record_contract_delivery("c1", "MXZ-2", Some(Role::Hauler), 5);
+
+        assert_eq!(database.ledger(), vec![entry]);
+        assert_eq!(
+            database.contract_deliveries("c1"),
+            vec![("MXZ-2".to_owned(), Some(Role::Hauler), 15)]
+        );
+    }
+}
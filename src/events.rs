@@ -0,0 +1,198 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use axum::{extract::Query, http::StatusCode, routing::get, Json, Router};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{accounts::Account, client::parse_time};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EventKind {
+    Navigation,
+    Extraction,
+    Trade,
+    ShipPurchase,
+    Contract,
+    /// Mounts installed or removed.
+    Modification,
+    /// Cargo jettisoned or transferred to another ship.
+    Cargo,
+    Survey,
+    Construction,
+    Error,
+}
+
+/// One game action, as a line of the event log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    /// When the action was taken, in RFC 3339.
+    pub time: String,
+    pub agent: String,
+    pub ship: Option<String>,
+    pub kind: EventKind,
+    /// What the API returned for the action, or the error and what was attempted.
+    pub payload: Value,
+}
+
+/// Appends every game action an agent takes to a JSON lines file, for analysis tools to read
+/// back with [`EventLog::read`].
+#[derive(Clone)]
+pub struct EventLog {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+fn open(path: &Path) -> File {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap()
+}
+
+impl EventLog {
+    pub fn open(path: PathBuf) -> Self {
+        Self {
+            file: Arc::new(Mutex::new(open(&path))),
+            path,
+        }
+    }
+
+    pub fn record(&self, agent: &str, ship: Option<&str>, kind: EventKind, payload: Value) {
+        let event = Event {
+            time: Utc::now().to_rfc3339(),
+            agent: agent.to_owned(),
+            ship: ship.map(str::to_owned),
+            kind,
+            payload,
+        };
+
+        let mut line = serde_json::to_string(&event).unwrap();
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            info!("[EVENTS] Failed to write to {}: {e}", self.path.display());
+        }
+    }
+
+    /// Makes sure every event recorded is on disk.
+    pub fn flush(&self) {
+        if let Err(e) = self.file.lock().unwrap().sync_all() {
+            info!("[EVENTS] Failed to flush {}: {e}", self.path.display());
+        }
+    }
+
+    /// Moves the log into the directory, carrying on in a new one.
+    pub fn archive(&self, directory: &Path) {
+        let mut file = self.file.lock().unwrap();
+
+        if let Some(name) = self.path.file_name() {
+            if let Err(e) = fs::rename(&self.path, directory.join(name)) {
+                info!("[EVENTS] Failed to archive {}: {e}", self.path.display());
+            }
+        }
+        *file = open(&self.path);
+    }
+
+    /// The events logged in the file, oldest first. Lines that can't be read, like one cut
+    /// short by a crash, are skipped.
+    pub fn read(path: &Path) -> impl Iterator<Item = Event> {
+        let lines = File::open(path)
+            .map(|f| BufReader::new(f).lines())
+            .into_iter()
+            .flatten();
+
+        lines
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+    }
+}
+
+/// Which events `/api/events` returns, all of them by default.
+#[derive(Deserialize)]
+struct Filter {
+    agent: Option<String>,
+    ship: Option<String>,
+    kind: Option<EventKind>,
+    /// Only events from this time on, in RFC 3339.
+    since: Option<String>,
+}
+
+impl Filter {
+    fn matches(&self, event: &Event, since: Option<DateTime<Utc>>) -> bool {
+        self.ship
+            .as_ref()
+            .is_none_or(|s| event.ship.as_ref() == Some(s))
+            && self.kind.is_none_or(|k| event.kind == k)
+            && since.is_none_or(|since| parse_time(&event.time) >= since)
+    }
+}
+
+async fn events(Query(filter): Query<Filter>) -> Result<Json<Vec<Event>>, (StatusCode, String)> {
+    let since = match &filter.since {
+        None => None,
+        Some(since) => Some(
+            DateTime::parse_from_rfc3339(since)
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid since: {e}")))?
+                .with_timezone(&Utc),
+        ),
+    };
+
+    let events = Account::all_from_env()
+        .into_iter()
+        .filter(|a| filter.agent.as_ref().is_none_or(|agent| a.name == *agent))
+        .flat_map(|a| EventLog::read(&a.path("EVENT_LOG", "events.jsonl")))
+        .filter(|e| filter.matches(e, since))
+        .collect();
+
+    Ok(Json(events))
+}
+
+/// The `/api/events` route, reading back the logs of every agent.
+pub fn routes() -> Router {
+    Router::new().route("/api/events", get(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn events_are_read_back_in_order() {
+        let path = std::env::temp_dir().join(format!("events-{}.jsonl", std::process::id()));
+        let log = EventLog::open(path.clone());
+
+        log.record(
+            "MXZ",
+            Some("MXZ-1"),
+            EventKind::Extraction,
+            json!({"symbol": "IRON_ORE", "units": 7}),
+        );
+        log.record("MXZ", None, EventKind::Error, json!({"code": 4000}));
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"time\":\"2024-01")
+            .unwrap();
+
+        let events: Vec<_> = EventLog::read(&path).collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].ship.as_deref(), Some("MXZ-1"));
+        assert_eq!(events[0].kind, EventKind::Extraction);
+        assert_eq!(events[0].payload["units"], 7);
+        assert_eq!(events[1].kind, EventKind::Error);
+
+        fs::remove_file(path).unwrap();
+    }
+}
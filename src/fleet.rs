@@ -0,0 +1,375 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    behaviours::{self, Order, SavedBehaviour},
+    dashboard::{ContractView, Dashboard, ShipView, Snapshot, WaypointView},
+    manager::{Manager, ManagerFactory},
+    roles::Role,
+    scheduler::{Behaviour, SchedulerHandle},
+    setup::command_ship,
+};
+use log::info;
+use spacedust::models::{Ship, ShipNavStatus};
+
+/// The role each ship is currently working as, shared so ships can find each other (miners
+/// looking for a hauler to offload to, for instance).
+#[derive(Clone, Default)]
+pub struct Roster {
+    roles: Arc<Mutex<HashMap<String, Role>>>,
+}
+
+impl Roster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn set(&self, ship_symbol: &str, role: Role) {
+        self.roles
+            .lock()
+            .unwrap()
+            .insert(ship_symbol.to_owned(), role);
+    }
+
+    pub fn role_of(&self, ship_symbol: &str) -> Option<Role> {
+        self.roles.lock().unwrap().get(ship_symbol).copied()
+    }
+
+    pub fn ships_with(&self, role: Role) -> Vec<String> {
+        let mut ships: Vec<_> = self
+            .roles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, r)| **r == role)
+            .map(|(s, _)| s.to_owned())
+            .collect();
+        ships.sort();
+        ships
+    }
+}
+
+/// How many miners a single hauler can keep up with.
+const MINERS_PER_HAULER: usize = 3;
+
+/// Adjusts the roles ships would naturally take to what the rest of the fleet needs: haulers
+/// and surveyors are useless without miners, and a hauler serves a few miners at most.
+pub fn desired_roles(natural: &HashMap<String, Role>) -> HashMap<String, Role> {
+    let miners = natural.values().filter(|r| **r == Role::Miner).count();
+    let max_haulers = miners.div_ceil(MINERS_PER_HAULER);
+
+    let mut ships: Vec<_> = natural.iter().collect();
+    ships.sort_by_key(|(ship_symbol, _)| ship_symbol.as_str());
+
+    let mut haulers = 0;
+    ships
+        .into_iter()
+        .map(|(ship_symbol, &role)| {
+            let role = match role {
+                Role::Surveyor if miners == 0 => Role::Probe,
+                Role::Hauler if haulers >= max_haulers => Role::Trader,
+                Role::Hauler => {
+                    haulers += 1;
+                    Role::Hauler
+                }
+                role => role,
+            };
+            (ship_symbol.to_owned(), role)
+        })
+        .collect()
+}
+
+struct ShipRoles {
+    /// The role the ship would take on its own, before balancing the fleet.
+    natural: Role,
+    current: Role,
+    /// Set when the role was chosen by hand, so balancing the fleet leaves it alone.
+    pinned: bool,
+}
+
+/// What can be asked of the fleet controller while it runs.
+pub enum FleetCommand {
+    /// Switches the ship to the role for good.
+    Pin(String, Role),
+    /// Carries out the order, then goes back to what the ship was doing.
+    Order(String, Order),
+}
+
+/// Decides what every ship it manages works as, handing the matching behaviour to the
+/// scheduler and changing it as the fleet grows.
+pub struct FleetController {
+    manager: Manager,
+    roster: Roster,
+    scheduler: SchedulerHandle,
+    dashboard: Dashboard,
+    ships: HashMap<String, ShipRoles>,
+    /// Ships driven by something else, e.g. the construction project.
+    reserved: HashSet<String>,
+    /// Whether there is a contract for contractors to work on, otherwise they trade.
+    contract_available: bool,
+}
+
+impl FleetController {
+    pub fn new(factory: &ManagerFactory, scheduler: SchedulerHandle, dashboard: Dashboard) -> Self {
+        Self {
+            manager: factory.get("FLEET"),
+            roster: factory.roster().clone(),
+            scheduler,
+            dashboard,
+            ships: HashMap::new(),
+            reserved: HashSet::new(),
+            contract_available: false,
+        }
+    }
+
+    pub fn reserve(&mut self, ship_symbol: &str) {
+        self.reserved.insert(ship_symbol.to_owned());
+    }
+
+    /// Starts working the ship under the role that suits it, resuming its saved state if it
+    /// was working under that role before. Does nothing for reserved ships and ships we already
+    /// manage.
+    pub fn add_ship(&mut self, ship: &Ship, saved: Option<SavedBehaviour>) {
+        if self.reserved.contains(&ship.symbol) || self.ships.contains_key(&ship.symbol) {
+            return;
+        }
+
+        let role = Role::assign(ship, self.contract_available);
+        self.ships.insert(
+            ship.symbol.to_owned(),
+            ShipRoles {
+                natural: role,
+                current: role,
+                pinned: false,
+            },
+        );
+
+        match saved.filter(|s| s.role() == Some(role)) {
+            Some(saved) => {
+                let mut behaviour = saved.restore();
+                behaviour.reconcile(ship);
+                info!(
+                    "[FLEET] Resuming {} as {}",
+                    ship.symbol,
+                    behaviour.describe()
+                );
+                self.start_with(&ship.symbol, role, behaviour);
+            }
+            None => {
+                info!("[FLEET] Adding {} as {role:?}", ship.symbol);
+                self.start(&ship.symbol, role);
+            }
+        }
+    }
+
+    fn start(&self, ship_symbol: &str, role: Role) {
+        self.start_with(ship_symbol, role, behaviours::for_role(role));
+    }
+
+    fn start_with(&self, ship_symbol: &str, role: Role, behaviour: Box<dyn Behaviour>) {
+        self.roster.set(ship_symbol, role);
+        self.scheduler.assign(ship_symbol, behaviour);
+
+        // Idle ships have nothing to do, don't wake them until they get another role.
+        self.scheduler.set_idle(ship_symbol, role == Role::Idle);
+    }
+
+    /// Switches the ship to another role. It takes effect once the current step is done.
+    pub fn reassign(&mut self, ship_symbol: &str, role: Role) {
+        let Some(roles) = self.ships.get_mut(ship_symbol) else {
+            return;
+        };
+
+        let previous = roles.current;
+        if previous == role {
+            return;
+        }
+
+        info!("[FLEET] Reassigning {ship_symbol} from {previous:?} to {role:?}");
+        roles.current = role;
+        self.start(ship_symbol, role);
+    }
+
+    pub fn handle(&mut self, command: FleetCommand) {
+        match command {
+            FleetCommand::Pin(ship_symbol, role) => {
+                let Some(roles) = self.ships.get_mut(&ship_symbol) else {
+                    info!("[FLEET] Not managing {ship_symbol}, can't make it a {role:?}");
+                    return;
+                };
+
+                roles.natural = role;
+                roles.pinned = true;
+                self.reassign(&ship_symbol, role);
+            }
+            FleetCommand::Order(ship_symbol, order) => {
+                // Whatever the ship was doing, the construction project of a reserved ship
+                // included, picks up where it left off afterwards.
+                info!("[FLEET] Ordering {ship_symbol}: {}", order.describe());
+                self.scheduler.interrupt(&ship_symbol, Box::new(order));
+            }
+        }
+    }
+
+    /// Checks whether there is a contract to work on, negotiating one with a ship docked at
+    /// headquarters if not, and has the ships able to take contracts take them or trade.
+    pub async fn refresh_contracts(&mut self) {
+        let client = self.manager.client();
+        let contractor = self.manager.contractor();
+
+        let mut available = contractor.available().await;
+        if !available {
+            let headquarters = client.get_my_agent().await.headquarters;
+            let docked = client.get_my_ships().await.into_iter().find(|s| {
+                s.nav.waypoint_symbol == headquarters && s.nav.status == ShipNavStatus::Docked
+            });
+            if let Some(ship) = docked {
+                contractor.negotiate(&ship.symbol).await;
+                available = contractor.available().await;
+            }
+        }
+
+        if available != self.contract_available {
+            info!("[FLEET] Contract available: {available}");
+        }
+        self.contract_available = available;
+
+        for roles in self.ships.values_mut().filter(|r| !r.pinned) {
+            roles.natural = match roles.natural {
+                Role::Contractor | Role::Trader if available => Role::Contractor,
+                Role::Contractor | Role::Trader => Role::Trader,
+                role => role,
+            };
+        }
+    }
+
+    /// Restarts ships left without a behaviour (e.g. after a panic), releases reserved ships
+    /// done with their project and reassigns roles to match the fleet's needs.
+    pub async fn rebalance(&mut self) {
+        self.refresh_contracts().await;
+
+        let mut released = Vec::new();
+        for status in self.scheduler.inspect().await {
+            info!("[FLEET] {status}");
+
+            let idle = status.behaviour.is_none() && !status.running;
+            let Some(roles) = self.ships.get(&status.ship_symbol) else {
+                if idle && status.queued == 0 && self.reserved.remove(&status.ship_symbol) {
+                    info!("[FLEET] {} is done with its project", status.ship_symbol);
+                    released.push(status.ship_symbol);
+                }
+                continue;
+            };
+            if idle {
+                info!(
+                    "[FLEET] {} has nothing to do, restarting it as {:?}",
+                    status.ship_symbol, roles.current
+                );
+                self.start(&status.ship_symbol, roles.current);
+            }
+        }
+
+        for ship_symbol in released {
+            let ship = self.manager.client().get_ship(&ship_symbol).await;
+            self.add_ship(&ship, None);
+        }
+
+        let natural = self
+            .ships
+            .iter()
+            .map(|(s, r)| (s.to_owned(), r.natural))
+            .collect();
+        for (ship_symbol, role) in desired_roles(&natural) {
+            if !self.ships[&ship_symbol].pinned {
+                self.reassign(&ship_symbol, role);
+            }
+        }
+
+        self.publish().await;
+    }
+
+    /// Sends the fleet's current state to the dashboard.
+    pub async fn publish(&self) {
+        let client = self.manager.client();
+        let statuses: HashMap<_, _> = self
+            .scheduler
+            .inspect()
+            .await
+            .into_iter()
+            .map(|s| (s.ship_symbol.to_owned(), s))
+            .collect();
+        let ships = client.get_my_ships().await;
+        let agent = client.get_my_agent().await;
+        let contracts = client.get_contracts().await;
+
+        let system = command_ship(&ships)
+            .map(|s| s.nav.system_symbol.to_owned())
+            .unwrap_or_default();
+        let waypoints = match system.as_str() {
+            "" => vec![],
+            system => self.manager.system_waypoints(system).await,
+        };
+
+        self.dashboard.publish(Snapshot {
+            agent: agent.symbol,
+            credits: agent.credits,
+            credit_history: vec![],
+            ships: ships
+                .iter()
+                .map(|s| {
+                    let role = self.ships.get(&s.symbol).map(|r| r.current);
+                    ShipView::new(s, role, statuses.get(&s.symbol))
+                })
+                .collect(),
+            contracts: contracts.iter().filter_map(ContractView::new).collect(),
+            system,
+            waypoints: waypoints.iter().map(WaypointView::new).collect(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fleet(roles: &[(&str, Role)]) -> HashMap<String, Role> {
+        roles.iter().map(|(s, r)| (s.to_string(), *r)).collect()
+    }
+
+    #[test]
+    fn support_ships_find_other_work_without_miners() {
+        let desired = desired_roles(&fleet(&[
+            ("A-1", Role::Contractor),
+            ("A-2", Role::Hauler),
+            ("A-3", Role::Surveyor),
+        ]));
+
+        assert_eq!(
+            desired,
+            fleet(&[
+                ("A-1", Role::Contractor),
+                ("A-2", Role::Trader),
+                ("A-3", Role::Probe),
+            ])
+        );
+    }
+
+    #[test]
+    fn one_hauler_per_three_miners() {
+        let desired = desired_roles(&fleet(&[
+            ("A-1", Role::Miner),
+            ("A-2", Role::Miner),
+            ("A-3", Role::Miner),
+            ("A-4", Role::Miner),
+            ("A-5", Role::Hauler),
+            ("A-6", Role::Hauler),
+            ("A-7", Role::Hauler),
+        ]));
+
+        assert_eq!(desired["A-5"], Role::Hauler);
+        assert_eq!(desired["A-6"], Role::Hauler);
+        assert_eq!(desired["A-7"], Role::Trader);
+    }
+}
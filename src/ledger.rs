@@ -0,0 +1,479 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+use spacedust::models::{
+    market_transaction, Contract, MarketTransaction, ShipModificationTransaction,
+    ShipyardTransaction,
+};
+
+use crate::{
+    accounts::Session, client::parse_time, database::Database, fleet::Roster, roles::Role,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EntryKind {
+    Trade,
+    Refuel,
+    ShipPurchase,
+    Modification,
+    Contract,
+}
+
+impl EntryKind {
+    /// Whether the entry pays for the ship itself rather than for its work.
+    fn is_investment(self) -> bool {
+        matches!(self, EntryKind::ShipPurchase | EntryKind::Modification)
+    }
+}
+
+/// A change to our credits, attributed to the ship that made it and the role it had then.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerEntry {
+    pub time: DateTime<Utc>,
+    /// None for contract payments nobody delivered for.
+    pub ship_symbol: Option<String>,
+    pub role: Option<Role>,
+    pub kind: EntryKind,
+    pub amount: i64,
+}
+
+/// Writes every credit delta an agent's ships make to its database.
+#[derive(Clone)]
+pub struct Ledger {
+    database: Database,
+    roster: Roster,
+}
+
+impl Ledger {
+    pub fn new(session: &Session) -> Self {
+        Self {
+            database: session.database.clone(),
+            roster: session.roster.clone(),
+        }
+    }
+
+    fn record(&self, time: &str, ship_symbol: &str, kind: EntryKind, amount: i64) {
+        self.database.record_ledger_entry(&LedgerEntry {
+            time: parse_time(time),
+            ship_symbol: Some(ship_symbol.to_owned()),
+            role: self.roster.role_of(ship_symbol),
+            kind,
+            amount,
+        });
+    }
+
+    pub fn market(&self, transaction: &MarketTransaction, kind: EntryKind) {
+        let amount = match transaction.r#type {
+            market_transaction::Type::Sell => transaction.total_price,
+            _ => -transaction.total_price,
+        };
+        self.record(
+            &transaction.timestamp,
+            &transaction.ship_symbol,
+            kind,
+            amount as i64,
+        );
+    }
+
+    /// The price of a ship we bought, which the ship has to earn back.
+    pub fn ship_purchase(&self, transaction: &ShipyardTransaction) {
+        self.record(
+            &transaction.timestamp,
+            &transaction.ship_symbol,
+            EntryKind::ShipPurchase,
+            -transaction.price as i64,
+        );
+    }
+
+    pub fn modification(&self, transaction: &ShipModificationTransaction) {
+        self.record(
+            &transaction.timestamp,
+            &transaction.ship_symbol,
+            EntryKind::Modification,
+            -transaction.total_price as i64,
+        );
+    }
+
+    pub fn contract_delivery(&self, contract_id: &str, ship_symbol: &str, units: i32) {
+        let role = self.roster.role_of(ship_symbol);
+        self.database
+            .record_contract_delivery(contract_id, ship_symbol, role, units);
+    }
+
+    /// Splits the contract's payments between the ships that delivered for it, by units
+    /// delivered. Both payments are counted once the contract is fulfilled.
+    pub fn contract_fulfilled(&self, contract: &Contract) {
+        let payment = &contract.terms.payment;
+        let total = payment.on_accepted as i64 + payment.on_fulfilled as i64;
+        let deliveries = self.database.contract_deliveries(&contract.id);
+
+        for entry in split(total, &deliveries, Utc::now()) {
+            self.database.record_ledger_entry(&entry);
+        }
+    }
+}
+
+/// Contract payment entries for each delivering ship, by their share of the units. Rounding is
+/// given to the last ship so the entries add up to the payment.
+fn split(
+    total: i64,
+    deliveries: &[(String, Option<Role>, i64)],
+    time: DateTime<Utc>,
+) -> Vec<LedgerEntry> {
+    let entry = |ship_symbol: Option<&String>, role, amount| LedgerEntry {
+        time,
+        ship_symbol: ship_symbol.cloned(),
+        role,
+        kind: EntryKind::Contract,
+        amount,
+    };
+
+    let units: i64 = deliveries.iter().map(|(_, _, u)| u).sum();
+    if units == 0 {
+        return vec![entry(None, Some(Role::Contractor), total)];
+    }
+
+    let mut remaining = total;
+    let mut entries: Vec<_> = deliveries
+        .iter()
+        .map(|(ship_symbol, role, delivered)| {
+            let amount = total * delivered / units;
+            remaining -= amount;
+            entry(Some(ship_symbol), *role, amount)
+        })
+        .collect();
+    if let Some(last) = entries.last_mut() {
+        last.amount += remaining;
+    }
+    entries
+}
+
+/// How a ship, or ships working a role, did financially.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Performance {
+    /// Credits made from work: sales and contract payments.
+    pub revenue: i64,
+    /// Credits spent working: cargo bought and fuel.
+    pub costs: i64,
+    /// Credits spent on ships and their modules.
+    pub investment: i64,
+    /// Revenue minus costs and investment.
+    pub profit: i64,
+    /// Profit over the investment, None for ships we didn't pay for.
+    pub roi: Option<f64>,
+    /// Revenue minus costs per hour since the first entry.
+    pub profit_per_hour: f64,
+    /// Hours from the first entry until the investment was earned back, or until it will be at
+    /// the current rate. None if it won't be.
+    pub payback_hours: Option<f64>,
+}
+
+impl Performance {
+    fn new(entries: &[&LedgerEntry], now: DateTime<Utc>) -> Self {
+        let mut performance = Self::default();
+        let Some(first) = entries.first() else {
+            return performance;
+        };
+
+        let mut balance = 0;
+        let mut paid_back_at = None;
+        for entry in entries {
+            match entry.amount {
+                amount if entry.kind.is_investment() => performance.investment -= amount,
+                amount if amount > 0 => performance.revenue += amount,
+                amount => performance.costs -= amount,
+            }
+
+            balance += entry.amount;
+            paid_back_at = match paid_back_at {
+                None if balance >= 0 && performance.investment > 0 => Some(entry.time),
+                Some(_) if balance < 0 => None,
+                at => at,
+            };
+        }
+
+        let hours = |to: DateTime<Utc>| (to - first.time).num_seconds().max(1) as f64 / 3600.0;
+        let operating = performance.revenue - performance.costs;
+        performance.profit = operating - performance.investment;
+        performance.profit_per_hour = operating as f64 / hours(now);
+
+        if performance.investment > 0 {
+            performance.roi = Some(performance.profit as f64 / performance.investment as f64);
+            performance.payback_hours = match paid_back_at {
+                Some(at) => Some(hours(at)),
+                None if performance.profit_per_hour > 0.0 => {
+                    Some(hours(now) - performance.profit as f64 / performance.profit_per_hour)
+                }
+                None => None,
+            };
+        }
+        performance
+    }
+}
+
+/// Profit and loss of the agent's ships, by ship, by role and by hour.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub total: Performance,
+    pub ships: BTreeMap<String, Performance>,
+    pub roles: BTreeMap<String, Performance>,
+    /// Profit made each hour, by the hour's start in RFC 3339.
+    pub hourly: BTreeMap<String, i64>,
+}
+
+impl Report {
+    /// Entries made while a ship had no role, like its purchase, count towards the role it had
+    /// last.
+    pub fn new(entries: &[LedgerEntry], now: DateTime<Utc>) -> Self {
+        let last_roles: HashMap<_, _> = entries
+            .iter()
+            .filter_map(|e| Some((e.ship_symbol.as_ref()?, e.role?)))
+            .collect();
+        let role_of = |entry: &LedgerEntry| {
+            entry
+                .role
+                .or_else(|| last_roles.get(entry.ship_symbol.as_ref()?).copied())
+                .map(|r| format!("{r:?}"))
+                .unwrap_or("Unassigned".to_owned())
+        };
+
+        let mut ships: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        let mut roles: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        let mut hourly = BTreeMap::new();
+        for entry in entries {
+            let ship_symbol = entry
+                .ship_symbol
+                .clone()
+                .unwrap_or("Unattributed".to_owned());
+            ships.entry(ship_symbol).or_default().push(entry);
+            roles.entry(role_of(entry)).or_default().push(entry);
+
+            let hour = entry.time.duration_trunc(Duration::hours(1)).unwrap();
+            *hourly.entry(hour.to_rfc3339()).or_default() += entry.amount;
+        }
+
+        let performances = |groups: BTreeMap<String, Vec<&LedgerEntry>>| {
+            (groups.into_iter())
+                .map(|(key, entries)| (key, Performance::new(&entries, now)))
+                .collect()
+        };
+        Self {
+            total: Performance::new(&entries.iter().collect::<Vec<_>>(), now),
+            ships: performances(ships),
+            roles: performances(roles),
+            hourly,
+        }
+    }
+}
+
+/// Credits per hour a ship working each role made, averaged over the ships that worked it.
+/// Leaves out roles worked for less than an hour, too little to go by.
+pub fn hourly_profit_by_role(entries: &[LedgerEntry], now: DateTime<Utc>) -> HashMap<Role, i64> {
+    let mut roles: HashMap<_, Vec<_>> = HashMap::new();
+    for entry in entries {
+        if let Some(role) = entry.role {
+            roles.entry(role).or_default().push(entry);
+        }
+    }
+
+    roles
+        .into_iter()
+        .filter(|(_, entries)| now - entries[0].time >= Duration::hours(1))
+        .map(|(role, entries)| {
+            let ships = (entries.iter())
+                .filter_map(|e| e.ship_symbol.as_ref())
+                .collect::<HashSet<_>>()
+                .len()
+                .max(1);
+            let performance = Performance::new(&entries, now);
+            (role, performance.profit_per_hour as i64 / ships as i64)
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct Filter {
+    agent: Option<String>,
+}
+
+/// The ledgers of the running agents, to report on.
+#[derive(Clone, Default)]
+pub struct Ledgers {
+    databases: Arc<Mutex<HashMap<String, Database>>>,
+}
+
+impl Ledgers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, agent: &str, database: Database) {
+        self.databases
+            .lock()
+            .unwrap()
+            .insert(agent.to_owned(), database);
+    }
+
+    /// The `/api/ledger` route.
+    pub fn routes(&self) -> Router {
+        Router::new()
+            .route("/api/ledger", get(reports))
+            .with_state(self.clone())
+    }
+}
+
+/// The profit and loss report of each agent.
+async fn reports(
+    State(ledgers): State<Ledgers>,
+    Query(filter): Query<Filter>,
+) -> Json<BTreeMap<String, Report>> {
+    let databases: Vec<_> = (ledgers.databases.lock().unwrap().iter())
+        .filter(|(name, _)| filter.agent.as_ref().is_none_or(|agent| *name == agent))
+        .map(|(name, database)| (name.to_owned(), database.clone()))
+        .collect();
+
+    // Reading the whole ledger blocks, so it is kept off the runtime's thread
+    let reports = tokio::task::spawn_blocking(move || {
+        (databases.into_iter())
+            .map(|(name, database)| (name, Report::new(&database.ledger(), Utc::now())))
+            .collect()
+    })
+    .await
+    .unwrap();
+
+    Json(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        time: &str,
+        ship: &str,
+        role: Option<Role>,
+        kind: EntryKind,
+        amount: i64,
+    ) -> LedgerEntry {
+        LedgerEntry {
+            time: parse_time(time),
+            ship_symbol: Some(ship.to_owned()),
+            role,
+            kind,
+            amount,
+        }
+    }
+
+    #[test]
+    fn contract_payments_are_split_by_units_delivered() {
+        let deliveries = vec![
+            ("MXZ-1".to_owned(), Some(Role::Contractor), 20),
+            ("MXZ-2".to_owned(), Some(Role::Hauler), 40),
+        ];
+
+        let entries = split(1000, &deliveries, Utc::now());
+        let amounts: Vec<_> = entries.iter().map(|e| e.amount).collect();
+        assert_eq!(amounts, vec![333, 667]);
+
+        let unattributed = split(1000, &[], Utc::now());
+        assert_eq!(unattributed[0].ship_symbol, None);
+        assert_eq!(unattributed[0].amount, 1000);
+    }
+
+    #[test]
+    fn profit_per_hour_is_averaged_over_a_role_s_ships() {
+        let entries = vec![
+            entry(
+                "2024-01-20T00:00:00Z",
+                "MXZ-1",
+                Some(Role::Miner),
+                EntryKind::Trade,
+                3000,
+            ),
+            entry(
+                "2024-01-20T01:00:00Z",
+                "MXZ-2",
+                Some(Role::Miner),
+                EntryKind::Trade,
+                1000,
+            ),
+            entry(
+                "2024-01-20T01:30:00Z",
+                "MXZ-3",
+                Some(Role::Trader),
+                EntryKind::Trade,
+                500,
+            ),
+        ];
+
+        let rates = hourly_profit_by_role(&entries, parse_time("2024-01-20T02:00:00Z"));
+        assert_eq!(rates, HashMap::from([(Role::Miner, 1000)]));
+    }
+
+    #[test]
+    fn ships_pay_back_their_price() {
+        let entries = vec![
+            entry(
+                "2024-01-20T00:00:00Z",
+                "MXZ-3",
+                None,
+                EntryKind::ShipPurchase,
+                -10_000,
+            ),
+            entry(
+                "2024-01-20T00:30:00Z",
+                "MXZ-3",
+                Some(Role::Miner),
+                EntryKind::Trade,
+                4_000,
+            ),
+            entry(
+                "2024-01-20T01:00:00Z",
+                "MXZ-3",
+                Some(Role::Miner),
+                EntryKind::Refuel,
+                -500,
+            ),
+            entry(
+                "2024-01-20T02:00:00Z",
+                "MXZ-3",
+                Some(Role::Miner),
+                EntryKind::Trade,
+                7_000,
+            ),
+            entry(
+                "2024-01-20T02:00:00Z",
+                "MXZ-1",
+                Some(Role::Trader),
+                EntryKind::Trade,
+                -300,
+            ),
+        ];
+
+        let report = Report::new(&entries, parse_time("2024-01-20T04:00:00Z"));
+
+        let miner = &report.ships["MXZ-3"];
+        assert_eq!(
+            (miner.revenue, miner.costs, miner.investment, miner.profit),
+            (11_000, 500, 10_000, 500)
+        );
+        assert_eq!(miner.roi, Some(0.05));
+        assert_eq!(miner.payback_hours, Some(2.0));
+        assert_eq!(report.roles["Miner"], *miner);
+        assert_eq!(report.roles["Trader"].roi, None);
+        assert_eq!(report.hourly["2024-01-20T02:00:00+00:00"], 6_700);
+        assert_eq!(report.total.profit, 200);
+    }
+}
@@ -9,8 +9,11 @@ use reqwest_middleware::{Middleware, Next, Result};
 use task_local_extensions::Extensions;
 use tokio::{sync::Mutex, time::sleep_until};
 
+use crate::metrics;
+
 #[derive(Debug, Default)]
 pub struct RateLimiter {
+    agent: String,
     queue: Mutex<VecDeque<Instant>>,
 }
 
@@ -18,9 +21,10 @@ const RATE_TTL: Duration = Duration::from_secs(1);
 const RPS: usize = 1;
 
 impl RateLimiter {
-    pub fn new() -> Self {
+    pub fn new(agent: &str) -> Self {
         let queue = VecDeque::new();
         Self {
+            agent: agent.to_owned(),
             queue: Mutex::new(queue),
         }
     }
@@ -52,7 +56,12 @@ impl RateLimiter {
     }
 
     async fn next_request(&self) {
+        let start = Instant::now();
+        metrics::rate_limiter_waiting(&self.agent, 1);
         self.sleep_until_allowed().await;
+        metrics::rate_limiter_waiting(&self.agent, -1);
+        metrics::record_rate_limiter_wait(&self.agent, start.elapsed());
+
         self.queue.lock().await.push_back(Instant::now());
     }
 }
@@ -1,17 +1,66 @@
+mod accounts;
+mod alerts;
+mod behaviours;
+mod cache;
+mod cassette;
+mod cli;
 mod client;
 mod configuration;
+mod construction;
+mod contracts;
+mod control;
+mod credentials;
+mod dashboard;
+mod database;
+mod events;
+mod fleet;
+mod ledger;
 mod limiter;
 mod manager;
+mod markets;
+mod metrics;
+mod outfitting;
+mod purchasing;
+mod reset;
+mod roles;
+mod scheduler;
 mod setup;
+mod shipyards;
+mod shutdown;
+mod states;
+mod strategy;
+mod supervisor;
+mod surveys;
+mod web;
 
 use log::{info, LevelFilter};
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
+use accounts::Account;
+use alerts::AlertMonitor;
+use clap::Parser;
+use cli::{Cli, Command};
 use client::Client;
 
+use configuration::optional_var;
+use construction::ConstructionProject;
+use control::Control;
+use credentials::Credentials;
+use dashboard::Dashboard;
+use fleet::FleetController;
+use ledger::Ledgers;
 use manager::ManagerFactory;
-use tokio::time::interval;
+use outfitting::Outfitter;
+use purchasing::{Buyer, RoiPolicy};
+use reset::ResetWatcher;
+use scheduler::Scheduler;
+use setup::{command_ship, Setup};
+use shutdown::Shutdown;
+use spacedust::models::WaypointType;
+use states::StateStore;
+use supervisor::supervise;
+use tokio::{sync::mpsc, time::interval};
 
 #[tokio::main(worker_threads = 1)]
 async fn main() {
@@ -30,12 +79,115 @@ async fn main() {
         .apply()
         .unwrap();
 
-    let client = Client::new("MAIN".into());
+    let cli = Cli::parse();
+    let account = cli.account();
+    match cli.command {
+        None | Some(Command::Run) => play(cli.agent.is_some().then_some(account)).await,
+        Some(command) => cli::execute(command, &account).await,
+    }
+}
+
+/// Plays the given agent, or every agent configured, serving the dashboard and the control API
+/// while they play. Returns once they have all shut down.
+async fn play(only: Option<Account>) {
+    let shutdown = Shutdown::listen();
+    let dashboard = Dashboard::new();
+    let control = Control::new();
+    let ledgers = Ledgers::new();
+    let web_address = optional_var("WEB_ADDR").unwrap_or("127.0.0.1:9898".to_owned());
+    let (web_dashboard, web_control, web_ledgers) =
+        (dashboard.clone(), control.clone(), ledgers.clone());
+    tokio::spawn(
+        async move { web::serve(&web_address, web_dashboard, web_control, web_ledgers).await },
+    );
+
+    let accounts = match only {
+        Some(account) => vec![account],
+        None => Account::all_from_env(),
+    };
+    let agents: Vec<_> = accounts
+        .into_iter()
+        .map(|account| {
+            tokio::spawn(play_agent(
+                account,
+                dashboard.clone(),
+                control.clone(),
+                ledgers.clone(),
+                shutdown.clone(),
+            ))
+        })
+        .collect();
+
+    for agent in agents {
+        agent.await.unwrap();
+    }
+    info!("[SHUTDOWN] Every agent stopped");
+    log::logger().flush();
+}
+
+/// Plays one agent until shut down, on a new account after each server reset. Starts it again
+/// after a backoff if it panics, without bringing down the other agents.
+async fn play_agent(
+    account: Account,
+    dashboard: Dashboard,
+    control: Control,
+    ledgers: Ledgers,
+    shutdown: Shutdown,
+) {
+    let name = format!("Agent {}", account.name);
+    supervise(&name, shutdown.clone(), || {
+        let (account, dashboard) = (account.clone(), dashboard.clone());
+        let (control, ledgers, shutdown) = (control.clone(), ledgers.clone(), shutdown.clone());
+        async move {
+            while !shutdown.is_requested()
+                && run(
+                    account.clone(),
+                    dashboard.clone(),
+                    control.clone(),
+                    ledgers.clone(),
+                    shutdown.clone(),
+                )
+                .await
+            {}
+        }
+    })
+    .await
+}
+
+/// Plays one agent: registers it if needed, then puts its fleet to work until shut down or the
+/// server resets. Returns whether it reset, the agent then being registered again.
+async fn run(
+    account: Account,
+    dashboard: Dashboard,
+    control: Control,
+    ledgers: Ledgers,
+    shutdown: Shutdown,
+) -> bool {
+    Setup::run_if_needed(&account).await;
+    let (stop, mut agent_shutdown) = shutdown.scope();
+    // Stops the agent's tasks if this panics
+    let _stopping = stop.guard();
+
+    let session = account.connect();
+    let client = Client::new(account.name.to_owned(), &session);
+    let states = StateStore::load(account.path("STATE_FILE", "ship_states.json"));
+
+    // The token is no good if the server reset while we weren't running
+    let status = client.get_status().await;
+    let registered = Credentials::load(&account.credentials_path())
+        .map_or(status.reset_date.to_owned(), |c| c.reset_date);
+    let watcher = Arc::new(ResetWatcher::new(&session, states.clone(), &registered));
+    if watcher.has_reset(&status).await {
+        watcher.start_over().await;
+        return true;
+    }
+    client.observe_reset().await;
 
     let ships = client.get_my_ships().await;
 
     info!(
-        "Found ships: {}",
+        "[{}] Found ships: {}",
+        account.name,
         ships
             .iter()
             .map(|s| s.symbol.to_owned())
@@ -43,60 +195,122 @@ async fn main() {
             .join(", ")
     );
 
-    let factory = ManagerFactory::new();
-
-    for d in &ships {
-        if d.symbol.ends_with("-1") {
-            info!("Skipping command ship...");
-            continue;
-        }
-
-        let ship_symbol = d.symbol.to_owned();
-        let manager = factory.get(&ship_symbol);
+    Outfitter::new(&account.name, &session).report(&ships);
 
-        tokio::spawn(async move {
-            loop {
-                manager.mine_loop(ship_symbol.as_str()).await;
-            }
-        });
+    let factory = ManagerFactory::new(&session);
+    let (watching_watcher, watcher_shutdown) = (watcher.clone(), agent_shutdown.clone());
+    let watching = tokio::spawn(async move {
+        supervise("Reset watcher", watcher_shutdown.clone(), || {
+            let (watcher, stop) = (watching_watcher.clone(), stop.clone());
+            let shutdown = watcher_shutdown.clone();
+            async move { watcher.run(stop, shutdown).await }
+        })
+        .await
+    });
+    let (scheduler, handle) = Scheduler::new(factory.clone(), states.clone());
+    tokio::spawn(scheduler.run(agent_shutdown.clone()));
 
-        tokio::time::sleep(Duration::from_secs(5)).await;
-    }
+    let monitor = Arc::new(AlertMonitor::new(&session, handle.clone()));
+    let monitor_shutdown = agent_shutdown.clone();
+    tokio::spawn(async move {
+        supervise("Alert monitor", monitor_shutdown.clone(), || {
+            let (monitor, shutdown) = (monitor.clone(), monitor_shutdown.clone());
+            async move { monitor.run(shutdown).await }
+        })
+        .await
+    });
 
-    let manager = factory.get("BUYER");
+    let mut controller = FleetController::new(&factory, handle.clone(), dashboard);
+    let mut commands = control.register(&account.name, handle.clone(), session.strategy.clone());
+    ledgers.register(&account.name, session.database.clone());
+    let construction_ship = optional_var("CONSTRUCTION_SHIP");
+    controller.refresh_contracts().await;
 
-    tokio::spawn(async move {
-        info!("[BUYER] Init manager done");
+    for d in &ships {
+        if construction_ship.as_deref() == Some(d.symbol.as_str()) {
+            let system_symbol = d.nav.system_symbol.as_str();
+            let jump_gate = factory
+                .get(&d.symbol)
+                .find_waypoint_for_type(system_symbol, WaypointType::JumpGate)
+                .await
+                .filter(|w| w.is_under_construction);
 
-        let client = Client::new("BUYER".into());
-        let current_system = client.get_ship("MXZ-1").await.nav.system_symbol;
+            match jump_gate {
+                Some(gate) => {
+                    info!(
+                        "Assigning {} to supply construction at {}",
+                        d.symbol, gate.symbol
+                    );
+                    controller.reserve(&d.symbol);
+                    let project = ConstructionProject::resume(
+                        states.get(&d.symbol),
+                        system_symbol,
+                        &gate.symbol,
+                    );
+                    handle.enqueue(&d.symbol, Box::new(project));
+                }
+                None => info!("No jump gate under construction in {system_symbol}"),
+            }
+        }
 
-        let mut stream = interval(Duration::from_secs(600));
-        loop {
-            stream.tick().await;
+        controller.add_ship(d, states.get(&d.symbol));
+    }
+    controller.rebalance().await;
 
-            info!("[BUYER] Checking for funds");
+    let buyer = Arc::new(Buyer::new(
+        &factory,
+        Box::new(RoiPolicy::from_env(session.strategy.clone())),
+    ));
+    let (new_ships, mut bought) = mpsc::channel(8);
 
-            let ships = client.get_my_ships().await;
-            if ships.len() >= 10 {
-                info!("[BUYER] Already have 10 ships, quitting...");
-                return;
-            }
+    // Ships are bought in the command ship's system, without it there is nowhere to shop
+    let buying = match command_ship(&ships) {
+        Some(command_ship) => {
+            let home_system = command_ship.nav.system_symbol.to_owned();
+            let buyer_shutdown = agent_shutdown.clone();
+            Some(tokio::spawn(async move {
+                supervise("Buyer", buyer_shutdown.clone(), || {
+                    let (buyer, home_system) = (buyer.clone(), home_system.clone());
+                    let (new_ships, shutdown) = (new_ships.clone(), buyer_shutdown.clone());
+                    async move { buyer.run(&home_system, new_ships, shutdown).await }
+                })
+                .await
+            }))
+        }
+        None => {
+            info!("[{}] No command ship, not buying ships", account.name);
+            None
+        }
+    };
 
-            let m = client.get_my_agent().await;
-            if m.credits > 165_000 {
-                info!("[BUYER] Enough credits for ship, attempting to buy");
-                manager
-                    .buy_ship_and_send_mining(&factory, current_system.as_str())
-                    .await
-            } else {
-                info!("[BUYER] Not enough funds");
+    let mut stream = interval(Duration::from_secs(600));
+    let mut refresh = interval(Duration::from_secs(60));
+    loop {
+        tokio::select! {
+            _ = stream.tick() => controller.rebalance().await,
+            _ = refresh.tick() => controller.publish().await,
+            Some(command) = commands.recv() => controller.handle(command),
+            Some(ship) = bought.recv() => {
+                controller.add_ship(&ship, None);
+                controller.rebalance().await;
             }
+            _ = agent_shutdown.requested() => break,
         }
-    });
+    }
 
-    let mut stream = interval(Duration::from_secs(60));
-    loop {
-        stream.tick().await;
+    info!("[{}] Shutting down", account.name);
+    handle.stop().await;
+    if let Some(buying) = buying {
+        buying.await.unwrap();
+    }
+    watching.await.unwrap();
+    session.database.checkpoint();
+    session.events.flush();
+    info!("[{}] Shut down", account.name);
+
+    if shutdown.is_requested() {
+        return false;
     }
+    watcher.start_over().await;
+    true
 }
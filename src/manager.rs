@@ -1,79 +1,463 @@
-use spacedust::models::{self, ShipType, Waypoint, WaypointTraitSymbol, WaypointType};
-
-use crate::client::Client;
+use spacedust::models::{
+    self, ShipNavStatus, ShipType, Waypoint, WaypointTraitSymbol, WaypointType,
+};
+
+use crate::{
+    accounts::Session,
+    client::Client,
+    contracts::Contractor,
+    fleet::Roster,
+    markets::{MarketIndex, TradeRoute},
+    outfitting::Outfitter,
+    roles::Role,
+    shipyards::ShipyardIndex,
+    strategy::Strategy,
+    surveys::SurveyPool,
+};
 
 use log::info;
 
 #[derive(Clone)]
-pub struct ManagerFactory {}
+pub struct ManagerFactory {
+    session: Session,
+    shipyards: ShipyardIndex,
+    markets: MarketIndex,
+    roster: Roster,
+    surveys: SurveyPool,
+}
 
 impl ManagerFactory {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(session: &Session) -> Self {
+        Self {
+            session: session.clone(),
+            shipyards: ShipyardIndex::with_database(session.database.clone()),
+            markets: MarketIndex::with_database(session.database.clone()),
+            roster: session.roster.clone(),
+            surveys: SurveyPool::with_database(session.database.clone()),
+        }
     }
 
     pub fn get(&self, log_context: &str) -> Manager {
-        Manager::new(log_context)
+        Manager::new(
+            log_context,
+            &self.session,
+            self.shipyards.clone(),
+            self.markets.clone(),
+            self.roster.clone(),
+            self.surveys.clone(),
+        )
+    }
+
+    pub fn roster(&self) -> &Roster {
+        &self.roster
     }
 }
 
+/// Trade runs making less than this aren't worth the fuel and time.
+const MIN_TRADE_PROFIT: i64 = 2_000;
+
+/// Haulers leave the asteroid field once their hold is this full, in percent.
+const HAULER_DEPARTURE_FILL: i32 = 75;
+
 #[derive(Clone)]
 pub struct Manager {
     log_context: String,
     client: Client,
+    shipyards: ShipyardIndex,
+    markets: MarketIndex,
+    roster: Roster,
+    surveys: SurveyPool,
+    outfitter: Outfitter,
+    contractor: Contractor,
+    strategy: Strategy,
 }
 
 impl Manager {
-    fn new(log_context: &str) -> Self {
-        let client = Client::new(log_context.to_owned());
+    fn new(
+        log_context: &str,
+        session: &Session,
+        shipyards: ShipyardIndex,
+        markets: MarketIndex,
+        roster: Roster,
+        surveys: SurveyPool,
+    ) -> Self {
+        let client = Client::new(log_context.to_owned(), session);
         Self {
             log_context: log_context.to_owned(),
             client,
+            shipyards,
+            contractor: Contractor::new(log_context, session, markets.clone()),
+            markets,
+            roster,
+            surveys,
+            outfitter: Outfitter::new(log_context, session),
+            strategy: session.strategy.clone(),
         }
     }
 
-    pub async fn buy_ship_and_send_mining(&self, factory: &ManagerFactory, system_symbol: &str) {
-        let ship = self
-            .purchase_ship(system_symbol, spacedust::models::ShipType::OreHound)
-            .await;
+    pub fn shipyards(&self) -> &ShipyardIndex {
+        &self.shipyards
+    }
+
+    pub fn markets(&self) -> &MarketIndex {
+        &self.markets
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    pub fn surveys(&self) -> &SurveyPool {
+        &self.surveys
+    }
+
+    /// Empties the hold into haulers waiting at the ship's waypoint, selling what's left at its
+    /// market, then puts the ship back in orbit.
+    pub async fn unload(&self, ship_symbol: &str) {
+        let context = &self.log_context;
+
+        if !self.offload_to_hauler(ship_symbol).await {
+            info!("[{context}] docking");
+            self.client.dock_ship(ship_symbol).await;
+
+            info!("[{context}] emptying");
+            self.sell_cargo(ship_symbol).await;
+            self.record_market(ship_symbol).await;
+        }
+
+        info!("[{context}] orbit");
+        self.client.orbit_ship(ship_symbol).await;
+    }
+
+    /// The market paying the most for the hauler's cargo, once the miners filled its hold
+    /// enough to leave the asteroid field. Keeps the hauler in orbit to receive cargo until then.
+    pub async fn haul_destination(&self, ship_symbol: &str, field: &str) -> Option<String> {
+        let context = &self.log_context;
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let cargo = &ship.cargo;
+        if cargo.units * 100 < cargo.capacity * HAULER_DEPARTURE_FILL {
+            if ship.nav.status == ShipNavStatus::Docked {
+                self.client.orbit_ship(ship_symbol).await;
+            }
+            return None;
+        }
+
+        let goods: Vec<_> = cargo
+            .inventory
+            .iter()
+            .map(|i| (i.symbol, i.units))
+            .collect();
+        let market = self
+            .markets
+            .best_market_for(&ship.nav.system_symbol, &goods)
+            .unwrap_or(field.to_owned());
+
         info!(
-            "[{}] Manager - Purchased ship: {} - {:?}",
-            self.log_context, ship.symbol, ship
+            "[{context}] Hauling {}/{} units to {market}",
+            cargo.units, cargo.capacity
         );
+        Some(market)
+    }
 
-        let asteroid_waypoint = self
-            .find_waypoint_for_type(system_symbol, WaypointType::AsteroidField)
+    /// Sells the hauler's cargo at the market it is at, refuelling while docked.
+    pub async fn sell_haul(&self, ship_symbol: &str) {
+        self.client.dock_ship(ship_symbol).await;
+        self.refuel(ship_symbol).await;
+        self.sell_cargo(ship_symbol).await;
+        self.record_market(ship_symbol).await;
+    }
+
+    /// Sells the hold at the market the ship is docked at, keeping the goods it pays less than
+    /// the sell floor for.
+    pub async fn sell_cargo(&self, ship_symbol: &str) {
+        let sell_floor = self.strategy.settings().sell_floor;
+        if sell_floor <= 0 {
+            self.client.sell_all(ship_symbol).await;
+            return;
+        }
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let goods = self
+            .client
+            .get_market(&ship.nav.system_symbol, &ship.nav.waypoint_symbol)
             .await
-            .unwrap();
-        info!(
-            "[{}] Manager - Found AsteroidField waypoint: {}",
-            self.log_context, asteroid_waypoint.symbol
-        );
-        self.client
-            .navigate(ship.symbol.as_str(), asteroid_waypoint.symbol.as_str())
+            .trade_goods
+            .unwrap_or_default();
+
+        for item in &ship.cargo.inventory {
+            match goods.iter().find(|g| g.symbol == item.symbol) {
+                Some(good) if good.sell_price < sell_floor => info!(
+                    "[{}] Keeping {}x{}, {} credits is below the sell floor of {sell_floor}",
+                    self.log_context,
+                    item.units,
+                    item.symbol.to_string(),
+                    good.sell_price
+                ),
+                _ => {
+                    self.client.sell(ship_symbol, item.symbol, item.units).await;
+                }
+            }
+        }
+    }
+
+    pub fn contractor(&self) -> &Contractor {
+        &self.contractor
+    }
+
+    pub fn outfitter(&self) -> &Outfitter {
+        &self.outfitter
+    }
+
+    /// Asks for a new contract if the ship is docked at headquarters.
+    pub async fn negotiate_at_headquarters(&self, ship_symbol: &str) {
+        let headquarters = self.client.get_my_agent().await.headquarters;
+        let ship = self.client.get_ship(ship_symbol).await;
+        if ship.nav.waypoint_symbol == headquarters && ship.nav.status == ShipNavStatus::Docked {
+            self.contractor.negotiate(ship_symbol).await;
+        }
+    }
+
+    /// The asteroid field of the ship's system.
+    pub async fn asteroid_field(&self, ship_symbol: &str) -> Option<String> {
+        let ship = self.client.get_ship(ship_symbol).await;
+        let field = self
+            .find_waypoint_for_type(&ship.nav.system_symbol, WaypointType::AsteroidField)
             .await;
 
-        let manager = factory.get(ship.symbol.as_str());
-        tokio::spawn(async move {
-            loop {
-                manager.mine_loop(ship.symbol.as_str()).await;
+        if field.is_none() {
+            info!(
+                "[{}] No asteroid field in {}",
+                self.log_context, ship.nav.system_symbol
+            );
+        }
+        field.map(|w| w.symbol)
+    }
+
+    /// Transfers the cargo to haulers waiting in orbit at the same waypoint. Returns true if
+    /// the hold is now empty.
+    async fn offload_to_hauler(&self, ship_symbol: &str) -> bool {
+        let context = &self.log_context;
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        if ship.cargo.units == 0 {
+            return false;
+        }
+        if ship.nav.status == ShipNavStatus::Docked {
+            self.client.orbit_ship(ship_symbol).await;
+        }
+
+        let mut inventory: Vec<_> = ship
+            .cargo
+            .inventory
+            .iter()
+            .map(|i| (i.symbol, i.units))
+            .collect();
+
+        for hauler_symbol in self.roster.ships_with(Role::Hauler) {
+            let hauler = self.client.get_ship(&hauler_symbol).await;
+            if hauler.nav.waypoint_symbol != ship.nav.waypoint_symbol
+                || hauler.nav.status != ShipNavStatus::InOrbit
+            {
+                continue;
+            }
+
+            let mut free = hauler.cargo.capacity - hauler.cargo.units;
+            for (trade_symbol, units) in inventory.iter_mut().filter(|(_, u)| *u > 0) {
+                let batch = i32::min(*units, free);
+                if batch == 0 {
+                    break;
+                }
+
+                match self
+                    .client
+                    .transfer_cargo(ship_symbol, &hauler_symbol, *trade_symbol, batch)
+                    .await
+                {
+                    Result::Ok(_) => {
+                        info!(
+                            "[{context}] Transferred {batch}x{} to {hauler_symbol}",
+                            trade_symbol.to_string()
+                        );
+                        *units -= batch;
+                        free -= batch;
+                    }
+                    Result::Err(e) => {
+                        info!("[{context}] Failed to transfer to {hauler_symbol}: {e}");
+                        break;
+                    }
+                }
             }
-        });
+        }
+
+        inventory.iter().all(|(_, u)| *u == 0)
     }
 
-    pub async fn mine_loop(&self, ship_symbol: &str) {
+    /// Records the market at the ship's current waypoint, if it has one.
+    async fn record_market(&self, ship_symbol: &str) {
+        let ship = self.client.get_ship(ship_symbol).await;
+        let waypoints = self
+            .find_waypoints_for_trait(&ship.nav.system_symbol, WaypointTraitSymbol::Marketplace)
+            .await;
+
+        if waypoints
+            .iter()
+            .any(|w| w.symbol == ship.nav.waypoint_symbol)
+        {
+            let market = self
+                .client
+                .get_market(&ship.nav.system_symbol, &ship.nav.waypoint_symbol)
+                .await;
+            self.markets.record(&market);
+        }
+    }
+
+    /// The market of the ship's system we know the least about.
+    pub async fn market_to_explore(&self, ship_symbol: &str) -> Option<String> {
         let context = &self.log_context;
-        info!("[{context}] docking");
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let system_symbol = ship.nav.system_symbol.as_str();
+        let symbols: Vec<_> = self
+            .find_waypoints_for_trait(system_symbol, WaypointTraitSymbol::Marketplace)
+            .await
+            .into_iter()
+            .map(|w| w.symbol)
+            .collect();
+
+        let Some(target) = self.markets.stalest(&symbols) else {
+            info!("[{context}] No market to explore in {system_symbol}");
+            return None;
+        };
+
+        info!("[{context}] Exploring {target}");
+        Some(target.to_owned())
+    }
+
+    /// Refreshes the prices of the market the ship is at (and the shipyard's if there is one),
+    /// refuelling while docked.
+    pub async fn visit_market(&self, ship_symbol: &str) {
         self.client.dock_ship(ship_symbol).await;
+        self.refuel(ship_symbol).await;
 
-        info!("[{context}] emptying");
-        self.client.sell_all(ship_symbol).await;
+        let ship = self.client.get_ship(ship_symbol).await;
+        let system_symbol = ship.nav.system_symbol.as_str();
+        let target = ship.nav.waypoint_symbol.as_str();
+        let market = self.client.get_market(system_symbol, target).await;
+        self.markets.record(&market);
 
-        info!("[{context}] orbit");
-        self.client.orbit_ship(ship_symbol).await;
+        let is_shipyard = self
+            .find_waypoints_for_trait(system_symbol, WaypointTraitSymbol::Shipyard)
+            .await
+            .iter()
+            .any(|w| w.symbol == target);
+        if is_shipyard {
+            let shipyard = self.client.get_shipyard(system_symbol, target).await;
+            self.shipyards.record(&shipyard);
+        }
+    }
+
+    /// The most profitable known trade route for the ship's free cargo space, if worth it.
+    pub async fn plan_trade(&self, ship_symbol: &str) -> Option<TradeRoute> {
+        let context = &self.log_context;
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let free = ship.cargo.capacity - ship.cargo.units;
 
-        info!("[{context}] extract");
-        self.client.extract_till_full(ship_symbol).await;
+        let route = self
+            .markets
+            .best_route(&ship.nav.system_symbol, free)
+            .filter(|r| r.profit() >= MIN_TRADE_PROFIT)?;
+
+        info!(
+            "[{context}] Trading {}x{} from {} ({}) to {} ({}), expecting {} credits",
+            route.units,
+            route.trade_symbol.to_string(),
+            route.buy_at,
+            route.buy_price,
+            route.sell_at,
+            route.sell_price,
+            route.profit()
+        );
+
+        Some(route)
+    }
+
+    /// Buys the route's goods at the market the ship is at, returning the units bought.
+    pub async fn buy_route(&self, ship_symbol: &str, route: &TradeRoute) -> i32 {
+        let context = &self.log_context;
+
+        self.client.dock_ship(ship_symbol).await;
+        self.refuel(ship_symbol).await;
+
+        let system_symbol = &self.client.get_ship(ship_symbol).await.nav.system_symbol;
+        let market = self.client.get_market(system_symbol, &route.buy_at).await;
+        self.markets.record(&market);
+        let Some(good) = market
+            .trade_goods
+            .unwrap_or_default()
+            .into_iter()
+            .find(|g| g.symbol == route.trade_symbol && g.purchase_price < route.sell_price)
+        else {
+            info!("[{context}] Route no longer profitable, skipping");
+            return 0;
+        };
+
+        let mut bought = 0;
+        while bought < route.units {
+            let batch = i32::min(route.units - bought, good.trade_volume);
+            match self
+                .client
+                .purchase_cargo(ship_symbol, route.trade_symbol, batch)
+                .await
+            {
+                Result::Ok(r) => {
+                    bought += r.transaction.units;
+                    info!(
+                        "[{context}] Bought {}x{} for {} credits. Total credits={}",
+                        r.transaction.units,
+                        r.transaction.trade_symbol,
+                        r.transaction.total_price,
+                        r.agent.credits
+                    );
+                }
+                Result::Err(e) => {
+                    info!(
+                        "[{context}] Failed to buy {batch}x{}: {e}",
+                        route.trade_symbol.to_string()
+                    );
+                    break;
+                }
+            }
+        }
+
+        bought
+    }
+
+    /// Sells the route's goods at the market the ship is at.
+    pub async fn sell_route(&self, ship_symbol: &str, route: &TradeRoute) {
+        self.client.dock_ship(ship_symbol).await;
+        self.refuel(ship_symbol).await;
+        self.client
+            .sell(ship_symbol, route.trade_symbol, route.units)
+            .await;
+
+        let system_symbol = &self.client.get_ship(ship_symbol).await.nav.system_symbol;
+        let market = self.client.get_market(system_symbol, &route.sell_at).await;
+        self.markets.record(&market);
+    }
+
+    /// Refuels the ship at the market it is docked at.
+    pub async fn refuel(&self, ship_symbol: &str) {
+        let context = &self.log_context;
+
+        match self.client.refuel(ship_symbol).await {
+            Result::Ok(r) => info!(
+                "[{context}] Refuelled for {} credits. Total credits={}",
+                r.transaction.total_price, r.agent.credits
+            ),
+            Result::Err(e) => info!("[{context}] Failed to refuel: {e}"),
+        }
     }
 
     pub async fn find_waypoint_for_type(
@@ -81,7 +465,7 @@ impl Manager {
         system_name: &str,
         waypoint_type: WaypointType,
     ) -> Option<Waypoint> {
-        let waypoints = self.client.get_system_waypoints(system_name).await;
+        let waypoints = self.system_waypoints(system_name).await;
 
         waypoints
             .iter()
@@ -89,39 +473,123 @@ impl Manager {
             .cloned()
     }
 
-    pub async fn find_waypoint_for_trait(
+    pub async fn find_waypoints_for_trait(
         &self,
         system_name: &str,
         waypoint_trait: WaypointTraitSymbol,
-    ) -> Option<Waypoint> {
-        let waypoints = self.client.get_system_waypoints(system_name).await;
+    ) -> Vec<Waypoint> {
+        let waypoints = self.system_waypoints(system_name).await;
 
         waypoints
+            .into_iter()
+            .filter(|w| w.traits.iter().any(|t| t.symbol == waypoint_trait))
+            .collect()
+    }
+
+    /// The system's waypoints from the database, fetched and saved along with the connections of
+    /// its jump gates the first time.
+    pub async fn system_waypoints(&self, system_name: &str) -> Vec<Waypoint> {
+        if let Some(waypoints) = self.client.database().waypoints(system_name) {
+            return waypoints;
+        }
+
+        let waypoints = self.client.get_system_waypoints(system_name).await;
+        for gate in waypoints
             .iter()
-            .find(|&w| w.traits.iter().any(|t| t.symbol == waypoint_trait))
-            .cloned()
+            .filter(|w| w.r#type == WaypointType::JumpGate && !w.is_under_construction)
+        {
+            let jump_gate = self.client.get_jump_gate(system_name, &gate.symbol).await;
+            self.client.database().save_jump_gate(&jump_gate);
+        }
+
+        info!(
+            "[{}] Saving {} waypoints of {system_name}",
+            self.log_context,
+            waypoints.len()
+        );
+        self.client
+            .database()
+            .save_waypoints(system_name, &waypoints);
+        waypoints
     }
 
-    pub async fn purchase_ship(&self, system_name: &str, ship_type: ShipType) -> Box<models::Ship> {
-        let shipyard = self
-            .find_waypoint_for_trait(system_name, WaypointTraitSymbol::Shipyard)
+    /// Refreshes the shipyard index for the system. Prices are only returned for shipyards
+    /// where one of our ships is present, the index keeps the last ones seen for the others.
+    pub async fn refresh_shipyards(&self, system_name: &str) {
+        for waypoint in self
+            .find_waypoints_for_trait(system_name, WaypointTraitSymbol::Shipyard)
             .await
-            .unwrap();
+        {
+            let shipyard = self
+                .client
+                .get_shipyard(system_name, &waypoint.symbol)
+                .await;
+            self.shipyards.record(&shipyard);
+        }
+    }
 
-        self.client
-            .purchase_ship(ship_type, shipyard.symbol.as_str())
+    /// Buys the ship type at the shipyard selling it cheapest, falling back on any shipyard
+    /// listing it when we haven't seen its price. Returns None when no shipyard sells it or the
+    /// purchase fails.
+    pub async fn purchase_ship(
+        &self,
+        system_name: &str,
+        ship_type: ShipType,
+    ) -> Option<Box<models::Ship>> {
+        if self.shipyards.sellers(system_name, ship_type).is_empty() {
+            self.refresh_shipyards(system_name).await;
+        }
+
+        let shipyard_symbol = match self.shipyards.cheapest(system_name, ship_type) {
+            Some(offer) => Some(offer.waypoint_symbol),
+            None => self
+                .shipyards
+                .sellers(system_name, ship_type)
+                .into_iter()
+                .next(),
+        };
+        let Some(shipyard_symbol) = shipyard_symbol else {
+            info!(
+                "[{}] Manager - No shipyard in {system_name} sells {}",
+                self.log_context,
+                ship_type.to_string()
+            );
+            return None;
+        };
+
+        info!(
+            "[{}] Manager - Buying {} at {shipyard_symbol}",
+            self.log_context,
+            ship_type.to_string()
+        );
+
+        match self
+            .client
+            .purchase_ship(ship_type, shipyard_symbol.as_str())
             .await
+        {
+            Result::Ok(ship) => Some(ship),
+            Result::Err(e) => {
+                info!(
+                    "[{}] Manager - Failed to buy {} at {shipyard_symbol}: {e}",
+                    self.log_context,
+                    ship_type.to_string()
+                );
+                None
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use spacedust::models::ship_mount::{self};
+    use crate::{accounts::Account, outfitting::Loadout};
 
     #[tokio::test]
     async fn test() {
-        let client = Client::new("bla".into());
+        let account = Account::all_from_env().remove(0);
+        let client = Client::new("bla".into(), &account.connect());
 
         let ships = client.get_my_ships().await;
 
@@ -129,16 +597,11 @@ mod tests {
             "{}",
             ships
                 .iter()
-                .map(|s| format!(
-                    "{}-{}",
+                .filter_map(|s| Loadout::for_role(s.registration.role).map(|l| format!(
+                    "{}: {}",
                     s.symbol,
-                    s.mounts
-                        .iter()
-                        .find(|s| s.symbol == ship_mount::Symbol::MiningLaserI
-                            || s.symbol == ship_mount::Symbol::MiningLaserIi)
-                        .unwrap()
-                        .name
-                ))
+                    l.diff(s)
+                )))
                 .collect::<Vec<_>>()
                 .join(", ")
         )
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use spacedust::models::{Market, MarketTradeGood, TradeSymbol};
+
+use crate::database::{self, Database};
+
+/// What we know about a market. Imports and exports are always visible, prices only when one
+/// of our ships was present.
+#[derive(Clone, Debug)]
+pub struct MarketListing {
+    pub market: Market,
+    pub prices_updated: Option<Instant>,
+}
+
+/// A buy-here, sell-there opportunity between two markets of a system.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeRoute {
+    pub trade_symbol: TradeSymbol,
+    pub buy_at: String,
+    pub sell_at: String,
+    pub buy_price: i32,
+    pub sell_price: i32,
+    pub units: i32,
+}
+
+impl TradeRoute {
+    pub fn profit(&self) -> i64 {
+        (self.sell_price - self.buy_price) as i64 * self.units as i64
+    }
+}
+
+/// Catalogue of the markets we've seen, shared between every manager.
+#[derive(Clone, Default)]
+pub struct MarketIndex {
+    listings: Arc<Mutex<HashMap<String, MarketListing>>>,
+    database: Option<Database>,
+}
+
+impl MarketIndex {
+    /// Starts from the markets saved in the database and saves every market recorded from now on.
+    pub fn with_database(database: Database) -> Self {
+        let listings = database
+            .markets()
+            .into_iter()
+            .map(|(market, updated)| {
+                let listing = MarketListing {
+                    market,
+                    prices_updated: updated.map(database::instant_at),
+                };
+                (listing.market.symbol.to_owned(), listing)
+            })
+            .collect();
+
+        Self {
+            listings: Arc::new(Mutex::new(listings)),
+            database: Some(database),
+        }
+    }
+
+    /// Records a `get_market` response, keeping the last known prices if none of our ships is
+    /// there anymore.
+    pub fn record(&self, market: &Market) {
+        let mut listings = self.listings.lock().unwrap();
+        let previous = listings.remove(&market.symbol);
+
+        let mut market = market.clone();
+        let prices_updated = match &market.trade_goods {
+            Some(_) => Some(Instant::now()),
+            None => previous.and_then(|p| {
+                market.trade_goods = p.market.trade_goods;
+                p.prices_updated
+            }),
+        };
+
+        if let Some(database) = &self.database {
+            database.save_market(&market, prices_updated.map(database::datetime_at));
+        }
+
+        listings.insert(
+            market.symbol.to_owned(),
+            MarketListing {
+                market,
+                prices_updated,
+            },
+        );
+    }
+
+    /// Of the given market waypoints, the one whose prices we've known the least recently.
+    pub fn stalest<'a>(&self, waypoint_symbols: &'a [String]) -> Option<&'a String> {
+        let listings = self.listings.lock().unwrap();
+
+        waypoint_symbols
+            .iter()
+            .min_by_key(|w| listings.get(*w).and_then(|l| l.prices_updated))
+    }
+
+    /// The most profitable route between two priced markets of the system for a ship with the
+    /// given free cargo space.
+    pub fn best_route(&self, system_symbol: &str, capacity: i32) -> Option<TradeRoute> {
+        let listings = self.listings.lock().unwrap();
+        let goods: Vec<(&str, &MarketTradeGood)> = listings
+            .values()
+            .filter(|l| l.market.symbol.starts_with(&format!("{system_symbol}-")))
+            .flat_map(|l| {
+                l.market
+                    .trade_goods
+                    .iter()
+                    .flatten()
+                    .map(|g| (l.market.symbol.as_str(), g))
+            })
+            .collect();
+
+        goods
+            .iter()
+            .flat_map(|(buy_at, buy)| {
+                goods
+                    .iter()
+                    .filter(move |(sell_at, sell)| sell_at != buy_at && sell.symbol == buy.symbol)
+                    .map(move |(sell_at, sell)| TradeRoute {
+                        trade_symbol: buy.symbol,
+                        buy_at: buy_at.to_string(),
+                        sell_at: sell_at.to_string(),
+                        buy_price: buy.purchase_price,
+                        sell_price: sell.sell_price,
+                        units: capacity,
+                    })
+            })
+            .filter(|r| r.profit() > 0)
+            .max_by_key(|r| r.profit())
+    }
+
+    /// The priced market of the system paying the most for the whole cargo.
+    pub fn best_market_for(
+        &self,
+        system_symbol: &str,
+        cargo: &[(TradeSymbol, i32)],
+    ) -> Option<String> {
+        let listings = self.listings.lock().unwrap();
+
+        listings
+            .values()
+            .filter(|l| l.market.symbol.starts_with(&format!("{system_symbol}-")))
+            .filter_map(|l| {
+                let goods = l.market.trade_goods.as_ref()?;
+                let value: i64 = cargo
+                    .iter()
+                    .filter_map(|(symbol, units)| {
+                        let good = goods.iter().find(|g| g.symbol == *symbol)?;
+                        Some(good.sell_price as i64 * *units as i64)
+                    })
+                    .sum();
+                Some((l.market.symbol.to_owned(), value))
+            })
+            .filter(|(_, value)| *value > 0)
+            .max_by_key(|(_, value)| *value)
+            .map(|(symbol, _)| symbol)
+    }
+
+    /// The lowest price a priced market of the system sells the good for.
+    pub fn cheapest_price(&self, system_symbol: &str, trade_symbol: TradeSymbol) -> Option<i32> {
+        self.priced_goods(system_symbol, trade_symbol)
+            .iter()
+            .map(|g| g.purchase_price)
+            .min()
+    }
+
+    /// The average price the priced markets of the system pay for the goods.
+    pub fn average_sell_price(
+        &self,
+        system_symbol: &str,
+        trade_symbols: &[TradeSymbol],
+    ) -> Option<i64> {
+        let prices: Vec<_> = trade_symbols
+            .iter()
+            .flat_map(|s| self.priced_goods(system_symbol, *s))
+            .map(|g| g.sell_price as i64)
+            .collect();
+
+        (!prices.is_empty()).then(|| prices.iter().sum::<i64>() / prices.len() as i64)
+    }
+
+    fn priced_goods(&self, system_symbol: &str, trade_symbol: TradeSymbol) -> Vec<MarketTradeGood> {
+        self.listings
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|l| l.market.symbol.starts_with(&format!("{system_symbol}-")))
+            .flat_map(|l| l.market.trade_goods.iter().flatten())
+            .filter(|g| g.symbol == trade_symbol)
+            .cloned()
+            .collect()
+    }
+
+    /// Markets of the system that export or exchange the good.
+    pub fn sellers(&self, system_symbol: &str, trade_symbol: TradeSymbol) -> Vec<MarketListing> {
+        self.listings
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|l| l.market.symbol.starts_with(&format!("{system_symbol}-")))
+            .filter(|l| {
+                l.market
+                    .exports
+                    .iter()
+                    .chain(l.market.exchange.iter())
+                    .any(|g| g.symbol == trade_symbol)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(symbol: &str, goods: &str) -> Market {
+        serde_json::from_str(&format!("{{\"symbol\":\"{symbol}\",\"exports\":[],\"imports\":[],\"exchange\":[],\"tradeGoods\":[{goods}]}}")).unwrap()
+    }
+
+    fn good(symbol: &str, purchase_price: i32, sell_price: i32) -> String {
+        format!("{{\"symbol\":\"{symbol}\",\"type\":\"EXCHANGE\",\"tradeVolume\":10,\"supply\":\"MODERATE\",\"purchasePrice\":{purchase_price},\"sellPrice\":{sell_price}}}")
+    }
+
+    #[test]
+    fn best_route_picks_largest_margin() {
+        let index = MarketIndex::default();
+        index.record(&market(
+            "X1-ZA40-A1",
+            &[good("IRON", 10, 8), good("FUEL", 70, 68)].join(","),
+        ));
+        index.record(&market(
+            "X1-ZA40-B2",
+            &[good("IRON", 30, 25), good("FUEL", 75, 72)].join(","),
+        ));
+        index.record(&market("X1-OTHER-C3", &good("IRON", 100, 95)));
+
+        let route = index.best_route("X1-ZA40", 20).unwrap();
+        assert_eq!(
+            route,
+            TradeRoute {
+                trade_symbol: TradeSymbol::Iron,
+                buy_at: "X1-ZA40-A1".into(),
+                sell_at: "X1-ZA40-B2".into(),
+                buy_price: 10,
+                sell_price: 25,
+                units: 20,
+            }
+        );
+        assert_eq!(route.profit(), 300);
+    }
+
+    #[test]
+    fn best_market_for_values_whole_cargo() {
+        let index = MarketIndex::default();
+        index.record(&market(
+            "X1-ZA40-A1",
+            &[good("IRON_ORE", 0, 20), good("COPPER_ORE", 0, 10)].join(","),
+        ));
+        index.record(&market("X1-ZA40-B2", &good("IRON_ORE", 0, 25)));
+
+        let cargo = [(TradeSymbol::IronOre, 10), (TradeSymbol::CopperOre, 10)];
+        assert_eq!(
+            index.best_market_for("X1-ZA40", &cargo),
+            Some("X1-ZA40-A1".to_owned())
+        );
+        assert_eq!(
+            index.best_market_for("X1-ZA40", &cargo[..1]),
+            Some("X1-ZA40-B2".to_owned())
+        );
+    }
+
+    #[test]
+    fn keeps_prices_when_no_ship_present() {
+        let index = MarketIndex::default();
+        index.record(&market("X1-ZA40-A1", &good("IRON", 10, 8)));
+        index.record(&market("X1-ZA40-B2", &good("IRON", 30, 25)));
+
+        let mut unpriced = market("X1-ZA40-B2", "");
+        unpriced.trade_goods = None;
+        index.record(&unpriced);
+
+        assert!(index.best_route("X1-ZA40", 20).is_some());
+        assert_eq!(
+            index.stalest(&["X1-ZA40-B2".into(), "X1-ZA40-C3".into()]),
+            Some(&"X1-ZA40-C3".to_owned())
+        );
+    }
+}
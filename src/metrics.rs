@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use axum::{routing::get, Router};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next, Result};
+use serde::Deserialize;
+use task_local_extensions::Extensions;
+
+use crate::scheduler::ShipStatus;
+
+lazy_static! {
+    static ref CREDITS: IntGaugeVec =
+        register_int_gauge_vec!("spacetraders_credits", "Credits of the agent", &["agent"])
+            .unwrap();
+    static ref CREDITS_PER_HOUR: IntGaugeVec = register_int_gauge_vec!(
+        "spacetraders_credits_per_hour",
+        "Credits earned per hour since the bot started",
+        &["agent"]
+    )
+    .unwrap();
+    static ref SHIP_STATE: IntGaugeVec = register_int_gauge_vec!(
+        "spacetraders_ship_state",
+        "1 for the behaviour each ship runs and whether it is running, waiting or paused",
+        &["ship", "behaviour", "state"]
+    )
+    .unwrap();
+    static ref EXTRACTED_UNITS: IntCounterVec = register_int_counter_vec!(
+        "spacetraders_extracted_units_total",
+        "Units extracted, by good",
+        &["agent", "good"]
+    )
+    .unwrap();
+    static ref SALES_REVENUE: IntCounterVec = register_int_counter_vec!(
+        "spacetraders_sales_revenue_total",
+        "Credits made selling cargo, by market and good",
+        &["agent", "market", "good"]
+    )
+    .unwrap();
+    static ref API_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "spacetraders_api_requests_total",
+        "API requests, by HTTP status and game error code",
+        &["agent", "method", "status", "error_code"]
+    )
+    .unwrap();
+    static ref API_LATENCY: HistogramVec = register_histogram_vec!(
+        "spacetraders_api_request_duration_seconds",
+        "API request latency, rate limiting excluded",
+        &["agent", "method"]
+    )
+    .unwrap();
+    static ref RATE_LIMITER_QUEUE: IntGaugeVec = register_int_gauge_vec!(
+        "spacetraders_rate_limiter_queue_depth",
+        "Requests waiting for the rate limiter",
+        &["agent"]
+    )
+    .unwrap();
+    static ref RATE_LIMITER_WAIT: HistogramVec = register_histogram_vec!(
+        "spacetraders_rate_limiter_wait_seconds",
+        "Time requests spent waiting for the rate limiter",
+        &["agent"],
+        vec![0.01, 0.1, 0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap();
+    /// The first credits seen for each agent, to work out credits per hour from.
+    static ref STARTING_CREDITS: Mutex<HashMap<String, (Instant, i64)>> = Mutex::default();
+    /// The labels each ship's state was last reported with, to clear them when it changes.
+    static ref SHIP_LABELS: Mutex<HashMap<String, [String; 2]>> = Mutex::default();
+}
+
+pub fn record_credits(agent: &str, credits: i64) {
+    CREDITS.with_label_values(&[agent]).set(credits);
+
+    let mut starting = STARTING_CREDITS.lock().unwrap();
+    let (since, start) = *starting
+        .entry(agent.to_owned())
+        .or_insert((Instant::now(), credits));
+
+    let hours = since.elapsed().as_secs_f64() / 3600.0;
+    if hours > 0.0 {
+        let per_hour = (credits - start) as f64 / hours;
+        CREDITS_PER_HOUR
+            .with_label_values(&[agent])
+            .set(per_hour as i64);
+    }
+}
+
+pub fn record_extraction(agent: &str, good: &str, units: i32) {
+    EXTRACTED_UNITS
+        .with_label_values(&[agent, good])
+        .inc_by(units as u64);
+}
+
+pub fn record_sale(agent: &str, market: &str, good: &str, revenue: i32) {
+    SALES_REVENUE
+        .with_label_values(&[agent, market, good])
+        .inc_by(revenue as u64);
+}
+
+pub fn record_ship_status(status: &ShipStatus) {
+    let state = if status.paused {
+        "paused"
+    } else if status.running {
+        "running"
+    } else if status.wake.is_some() {
+        "waiting"
+    } else {
+        "idle"
+    };
+    let labels = [
+        status.name.clone().unwrap_or("none".to_owned()),
+        state.to_owned(),
+    ];
+
+    let mut last = SHIP_LABELS.lock().unwrap();
+    if let Some([behaviour, state]) = last.get(&status.ship_symbol) {
+        let _ = SHIP_STATE.remove_label_values(&[&status.ship_symbol, behaviour, state]);
+    }
+
+    SHIP_STATE
+        .with_label_values(&[&status.ship_symbol, &labels[0], &labels[1]])
+        .set(1);
+    last.insert(status.ship_symbol.to_owned(), labels);
+}
+
+pub fn rate_limiter_waiting(agent: &str, delta: i64) {
+    RATE_LIMITER_QUEUE.with_label_values(&[agent]).add(delta);
+}
+
+pub fn record_rate_limiter_wait(agent: &str, wait: Duration) {
+    RATE_LIMITER_WAIT
+        .with_label_values(&[agent])
+        .observe(wait.as_secs_f64());
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ErrorCode,
+}
+
+#[derive(Deserialize)]
+struct ErrorCode {
+    code: i32,
+}
+
+/// Counts and times every API request, reading the game's error code out of failed ones.
+pub struct MetricsMiddleware {
+    agent: String,
+}
+
+impl MetricsMiddleware {
+    pub fn new(agent: &str) -> Self {
+        Self {
+            agent: agent.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for MetricsMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let method = req.method().to_string();
+        let start = Instant::now();
+        let response = next.run(req, extensions).await;
+        API_LATENCY
+            .with_label_values(&[&self.agent, &method])
+            .observe(start.elapsed().as_secs_f64());
+
+        let response = match response {
+            Result::Ok(r) => r,
+            Result::Err(e) => {
+                API_REQUESTS
+                    .with_label_values(&[&self.agent, &method, "none", ""])
+                    .inc();
+                return Err(e);
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            API_REQUESTS
+                .with_label_values(&[&self.agent, &method, status.as_str(), ""])
+                .inc();
+            return Ok(response);
+        }
+
+        // The body has to be read to find the error code, so the response is rebuilt around it
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in response.headers() {
+            builder = builder.header(name, value);
+        }
+        let body = response.bytes().await?;
+        let error_code = serde_json::from_slice::<ErrorBody>(&body)
+            .map(|b| b.error.code.to_string())
+            .unwrap_or_default();
+
+        API_REQUESTS
+            .with_label_values(&[&self.agent, &method, status.as_str(), &error_code])
+            .inc();
+        Ok(Response::from(builder.body(body).unwrap()))
+    }
+}
+
+async fn metrics() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+
+    String::from_utf8(buffer).unwrap()
+}
+
+/// The `/metrics` route for Prometheus to scrape.
+pub fn routes() -> Router {
+    Router::new().route("/metrics", get(metrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn exposes_recorded_metrics() {
+        record_credits("TEST", 175_000);
+        record_sale("TEST", "X1-ZA40-B7", "IRON_ORE", 200);
+        record_sale("TEST", "X1-ZA40-B7", "IRON_ORE", 300);
+
+        let text = metrics().await;
+        assert!(text.contains("spacetraders_credits{agent=\"TEST\"} 175000"));
+        assert!(text.contains(
+            "spacetraders_sales_revenue_total{agent=\"TEST\",good=\"IRON_ORE\",market=\"X1-ZA40-B7\"} 500"
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use spacedust::models::{ship_mount, Ship, ShipRole, TradeSymbol, Waypoint, WaypointTraitSymbol};
+
+use crate::{accounts::Session, client::Client, strategy::Strategy};
+
+/// The mounts we want on every ship of a role.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loadout {
+    pub mounts: Vec<ship_mount::Symbol>,
+}
+
+impl Loadout {
+    pub fn for_role(role: ShipRole) -> Option<Self> {
+        use ship_mount::Symbol::*;
+
+        let mounts = match role {
+            ShipRole::Excavator => vec![MiningLaserIi, SurveyorI],
+            ShipRole::Surveyor => vec![SurveyorIi],
+            ShipRole::Command => vec![SensorArrayI, MiningLaserI],
+            _ => return None,
+        };
+
+        Some(Self { mounts })
+    }
+
+    /// Compares the ship's mounts with the loadout, treating both as multisets.
+    pub fn diff(&self, ship: &Ship) -> LoadoutDiff {
+        let mut surplus: Vec<_> = ship.mounts.iter().map(|m| m.symbol).collect();
+        let mut missing = Vec::new();
+
+        for wanted in &self.mounts {
+            match surplus.iter().position(|m| m == wanted) {
+                Some(i) => {
+                    surplus.remove(i);
+                }
+                None => missing.push(*wanted),
+            }
+        }
+
+        LoadoutDiff { missing, surplus }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadoutDiff {
+    pub missing: Vec<ship_mount::Symbol>,
+    pub surplus: Vec<ship_mount::Symbol>,
+}
+
+impl LoadoutDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl std::fmt::Display for LoadoutDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = |mounts: &[ship_mount::Symbol]| {
+            mounts
+                .iter()
+                .map(|m| format!("{m:?}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        write!(
+            f,
+            "missing=[{}], surplus=[{}]",
+            names(&self.missing),
+            names(&self.surplus)
+        )
+    }
+}
+
+/// A mount to buy at a market and install at a shipyard.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Upgrade {
+    pub mount: ship_mount::Symbol,
+    pub market: String,
+    pub shipyard: String,
+    /// The surplus mount to remove to make room, if the ship has no free mounting point.
+    pub replacing: Option<ship_mount::Symbol>,
+}
+
+/// Mounts are bought from markets as trade goods sharing the mount's symbol.
+pub fn mount_trade_symbol(mount: ship_mount::Symbol) -> TradeSymbol {
+    serde_json::from_value(serde_json::to_value(mount).unwrap()).unwrap()
+}
+
+/// Buys and installs the mounts missing from a ship's loadout whenever we can afford them.
+#[derive(Clone)]
+pub struct Outfitter {
+    log_context: String,
+    client: Client,
+    strategy: Strategy,
+    /// Last seen mount prices, so we don't travel to a market for a mount we can't afford.
+    prices: Arc<Mutex<HashMap<TradeSymbol, i64>>>,
+}
+
+impl Outfitter {
+    pub fn new(log_context: &str, session: &Session) -> Self {
+        Self {
+            log_context: log_context.to_owned(),
+            client: Client::new(log_context.to_owned(), session),
+            strategy: session.strategy.clone(),
+            prices: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn report(&self, ships: &[Ship]) {
+        let context = &self.log_context;
+
+        for ship in ships {
+            match Loadout::for_role(ship.registration.role) {
+                Some(loadout) => {
+                    let diff = loadout.diff(ship);
+                    if diff.is_empty() {
+                        info!("[{context}] {} loadout complete", ship.symbol)
+                    } else {
+                        info!("[{context}] {} loadout {diff}", ship.symbol)
+                    }
+                }
+                None => info!(
+                    "[{context}] {} has no loadout for role {:?}",
+                    ship.symbol, ship.registration.role
+                ),
+            }
+        }
+    }
+
+    /// The first missing mount of the ship's loadout, if a market in the system sells it, there
+    /// is a shipyard to install it and we can afford it as far as we know.
+    pub async fn plan_upgrade(&self, ship_symbol: &str) -> Option<Upgrade> {
+        let context = &self.log_context;
+
+        let ship = self.client.get_ship(ship_symbol).await;
+        let loadout = Loadout::for_role(ship.registration.role)?;
+
+        let diff = loadout.diff(&ship);
+        let &mount = diff.missing.first()?;
+
+        let full = ship.mounts.len() as i32 >= ship.frame.mounting_points;
+        if full && diff.surplus.is_empty() {
+            return None;
+        }
+
+        let trade_symbol = mount_trade_symbol(mount);
+        let known_price = self.prices.lock().unwrap().get(&trade_symbol).copied();
+        let credit_reserve = self.strategy.settings().credit_reserve;
+        if self.client.get_my_agent().await.credits
+            < credit_reserve + known_price.unwrap_or_default()
+        {
+            return None;
+        }
+
+        let system_symbol = ship.nav.system_symbol.as_str();
+        let waypoints = self.client.get_system_waypoints(system_symbol).await;
+
+        let Some(market) = self
+            .find_market_selling(system_symbol, &waypoints, trade_symbol)
+            .await
+        else {
+            info!("[{context}] No market sells {mount:?}, keeping current loadout");
+            return None;
+        };
+
+        let Some(shipyard) = waypoints.iter().find(|w| {
+            w.traits
+                .iter()
+                .any(|t| t.symbol == WaypointTraitSymbol::Shipyard)
+        }) else {
+            info!("[{context}] No shipyard in {system_symbol} to install {mount:?}");
+            return None;
+        };
+
+        info!("[{context}] Upgrading loadout, {diff}");
+
+        Some(Upgrade {
+            mount,
+            market: market.symbol,
+            shipyard: shipyard.symbol.to_owned(),
+            // Checked above that there is a surplus mount to make room.
+            replacing: diff.surplus.first().copied().filter(|_| full),
+        })
+    }
+
+    /// Buys the upgrade's mount at the market the ship is at. Returns whether it was bought.
+    pub async fn buy_mount(&self, ship_symbol: &str, upgrade: &Upgrade) -> bool {
+        let context = &self.log_context;
+        let mount = upgrade.mount;
+        let trade_symbol = mount_trade_symbol(mount);
+
+        self.client.dock_ship(ship_symbol).await;
+
+        let system_symbol = self.client.get_ship(ship_symbol).await.nav.system_symbol;
+        let price = self
+            .client
+            .get_market(&system_symbol, &upgrade.market)
+            .await
+            .trade_goods
+            .unwrap_or_default()
+            .iter()
+            .find(|g| g.symbol == trade_symbol)
+            .map(|g| g.purchase_price as i64);
+        if let Some(price) = price {
+            self.prices.lock().unwrap().insert(trade_symbol, price);
+        }
+        let credits = self.client.get_my_agent().await.credits;
+
+        match price {
+            Some(price) if price + self.strategy.settings().credit_reserve <= credits => match self
+                .client
+                .purchase_cargo(ship_symbol, trade_symbol, 1)
+                .await
+            {
+                Result::Ok(r) => {
+                    info!(
+                        "[{context}] Bought {mount:?} for {} credits. Total credits={}",
+                        r.transaction.total_price, r.agent.credits
+                    );
+                    true
+                }
+                Result::Err(e) => {
+                    info!("[{context}] Failed to buy {mount:?}: {e}");
+                    false
+                }
+            },
+            _ => {
+                info!("[{context}] Can't afford {mount:?} (price={price:?}, credits={credits})");
+                false
+            }
+        }
+    }
+
+    /// Installs the upgrade's mount at the shipyard the ship is at, removing the mount it
+    /// replaces first.
+    pub async fn install_mount(&self, ship_symbol: &str, upgrade: &Upgrade) {
+        let context = &self.log_context;
+        let mount = upgrade.mount;
+
+        self.client.dock_ship(ship_symbol).await;
+
+        if let Some(removed) = upgrade.replacing {
+            match self
+                .client
+                .remove_mount(ship_symbol, mount_trade_symbol(removed))
+                .await
+            {
+                Result::Ok(_) => info!("[{context}] Removed {removed:?}"),
+                Result::Err(e) => info!("[{context}] Failed to remove {removed:?}: {e}"),
+            }
+        }
+
+        match self
+            .client
+            .install_mount(ship_symbol, mount_trade_symbol(mount))
+            .await
+        {
+            Result::Ok(r) => info!(
+                "[{context}] Installed {mount:?} for {} credits. Mounts are now {}",
+                r.transaction.total_price,
+                r.mounts
+                    .iter()
+                    .map(|m| m.name.to_owned())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Result::Err(e) => info!("[{context}] Failed to install {mount:?}: {e}"),
+        }
+    }
+
+    async fn find_market_selling(
+        &self,
+        system_symbol: &str,
+        waypoints: &[Waypoint],
+        trade_symbol: TradeSymbol,
+    ) -> Option<Waypoint> {
+        for waypoint in waypoints.iter().filter(|w| {
+            w.traits
+                .iter()
+                .any(|t| t.symbol == WaypointTraitSymbol::Marketplace)
+        }) {
+            let market = self
+                .client
+                .get_market(system_symbol, &waypoint.symbol)
+                .await;
+
+            if market
+                .exports
+                .iter()
+                .chain(market.exchange.iter())
+                .any(|g| g.symbol == trade_symbol)
+            {
+                return Some(waypoint.clone());
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_trade_symbols() {
+        assert_eq!(
+            mount_trade_symbol(ship_mount::Symbol::MiningLaserIi),
+            TradeSymbol::MountMiningLaserIi
+        );
+        assert_eq!(
+            mount_trade_symbol(ship_mount::Symbol::SurveyorI),
+            TradeSymbol::MountSurveyorI
+        );
+    }
+}
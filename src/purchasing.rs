@@ -0,0 +1,487 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::Utc;
+use log::info;
+use spacedust::models::{
+    ship_mount, Contract, Ship, ShipRole, ShipType, ShipyardShip, TradeSymbol,
+};
+use tokio::{sync::mpsc, time::interval};
+
+use crate::{
+    client::Client,
+    configuration::{optional_var, parse_var},
+    contracts::remaining_deliveries,
+    ledger::hourly_profit_by_role,
+    manager::{Manager, ManagerFactory},
+    roles::{cargo_capacity, Role},
+    shutdown::Shutdown,
+    strategy::Strategy,
+};
+
+/// A ship for sale at a given shipyard, as seen by a ship present there.
+#[derive(Clone, Debug)]
+pub struct ShipOffer {
+    pub waypoint_symbol: String,
+    pub ship: ShipyardShip,
+}
+
+impl ShipOffer {
+    pub fn price(&self) -> i64 {
+        self.ship.purchase_price as i64
+    }
+}
+
+/// What we know about what ships earn, to estimate the income of the ships on offer from.
+#[derive(Clone, Debug, Default)]
+pub struct Earnings {
+    /// Credits per hour a ship working each role made so far, from the ledger.
+    pub observed: HashMap<Role, i64>,
+    /// Profit per unit of cargo of the best trade route between known market prices.
+    pub trade_margin: i64,
+    /// Profit per unit of cargo delivered for the best contract on offer, buying its goods at
+    /// known market prices.
+    pub contract_margin: i64,
+    /// Average price known markets pay for the ores our miners extract.
+    pub ore_price: Option<i64>,
+}
+
+pub trait PurchasePolicy: Send + Sync {
+    /// Picks the offer to buy given the current credits, fleet and earnings, if any is worth
+    /// buying.
+    fn choose(
+        &self,
+        credits: i64,
+        fleet: &[Ship],
+        offers: &[ShipOffer],
+        earnings: &Earnings,
+    ) -> Option<ShipOffer>;
+}
+
+/// The role a ship of the given type is registered with once bought.
+pub fn role_for_type(ship_type: ShipType) -> ShipRole {
+    match ship_type {
+        ShipType::Probe => ShipRole::Satellite,
+        ShipType::MiningDrone | ShipType::OreHound => ShipRole::Excavator,
+        ShipType::SiphonDrone => ShipRole::Excavator,
+        ShipType::Interceptor => ShipRole::Interceptor,
+        ShipType::LightHauler | ShipType::HeavyFreighter => ShipRole::Hauler,
+        ShipType::CommandFrigate => ShipRole::Command,
+        ShipType::Explorer => ShipRole::Explorer,
+        ShipType::LightShuttle => ShipRole::Transport,
+        ShipType::RefiningFreighter => ShipRole::Refinery,
+        ShipType::Surveyor => ShipRole::Surveyor,
+    }
+}
+
+/// Seconds between two extractions of a mining ship, cooldown included.
+const EXTRACTION_INTERVAL: i64 = 80;
+
+/// Seconds a trade or contract run takes, buying, travelling and selling.
+const TRIP_DURATION: i64 = 900;
+
+/// The goods our miners extract and sell.
+const ORES: &[TradeSymbol] = &[
+    TradeSymbol::IronOre,
+    TradeSymbol::CopperOre,
+    TradeSymbol::AluminumOre,
+    TradeSymbol::SilverOre,
+    TradeSymbol::GoldOre,
+    TradeSymbol::PlatinumOre,
+    TradeSymbol::QuartzSand,
+    TradeSymbol::SiliconCrystals,
+];
+
+/// Credits per unit the contract pays above what its remaining goods cost, given the price to
+/// buy each good at.
+fn contract_margin(contract: &Contract, price: impl Fn(TradeSymbol) -> Option<i32>) -> Option<i64> {
+    let deliveries = remaining_deliveries(contract);
+    let units: i64 = deliveries.iter().map(|(_, _, units)| *units as i64).sum();
+    if units == 0 {
+        return None;
+    }
+
+    let cost = deliveries
+        .iter()
+        .map(|(trade_symbol, _, units)| Some(price(*trade_symbol)? as i64 * *units as i64))
+        .sum::<Option<i64>>()?;
+    let payment = contract.terms.payment.on_fulfilled as i64
+        + if contract.accepted {
+            0
+        } else {
+            contract.terms.payment.on_accepted as i64
+        };
+
+    Some((payment - cost) / units)
+}
+
+/// Buys the ship with the best return on investment that fits within the credit reserve and
+/// the per-role fleet caps.
+pub struct RoiPolicy {
+    pub strategy: Strategy,
+    pub max_ships: usize,
+    /// How many ships each role takes at most. Roles left out aren't bought for.
+    pub role_caps: HashMap<Role, usize>,
+    /// Offers taking longer than this to pay for themselves are not bought.
+    pub max_payback_hours: i64,
+    /// Average sell price of the goods our miners bring back, until we know market prices.
+    pub average_ore_price: i64,
+}
+
+impl RoiPolicy {
+    pub fn from_env(strategy: Strategy) -> Self {
+        let var = |key: &str, default: i64| parse_var(key, optional_var(key)).unwrap_or(default);
+
+        Self {
+            strategy,
+            max_ships: var("MAX_SHIPS", 10) as usize,
+            // Traders take contracts too, the fleet switches them over when one comes up
+            role_caps: HashMap::from([
+                (Role::Miner, var("MAX_MINERS", 10) as usize),
+                (Role::Surveyor, var("MAX_SURVEYORS", 2) as usize),
+                (Role::Hauler, var("MAX_HAULERS", 3) as usize),
+                (Role::Trader, var("MAX_TRADERS", 5) as usize),
+                (Role::Probe, var("MAX_PROBES", 3) as usize),
+            ]),
+            max_payback_hours: var("MAX_PAYBACK_HOURS", 48),
+            average_ore_price: var("AVERAGE_ORE_PRICE", 40),
+        }
+    }
+
+    /// Estimated credits per hour for the ship in the role it would get. What ships in that
+    /// role made so far goes first, then what its lasers or hold would make at known prices.
+    /// Surveyors and probes only help the other ships earn, so they earn nothing of their own
+    /// until the ledger shows otherwise, and so do haulers, which sell what the miners extract.
+    pub fn estimate_hourly_income(&self, ship: &ShipyardShip, earnings: &Earnings) -> i64 {
+        let role = Role::for_offer(ship);
+        if let Some(&observed) = earnings.observed.get(&role) {
+            return observed;
+        }
+
+        match role {
+            Role::Miner => {
+                let strength: i64 = ship
+                    .mounts
+                    .iter()
+                    .filter(|m| {
+                        matches!(
+                            m.symbol,
+                            ship_mount::Symbol::MiningLaserI
+                                | ship_mount::Symbol::MiningLaserIi
+                                | ship_mount::Symbol::MiningLaserIii
+                        )
+                    })
+                    .map(|m| m.strength.unwrap_or_default() as i64)
+                    .sum();
+                let ore_price = earnings.ore_price.unwrap_or(self.average_ore_price);
+
+                strength * ore_price * 3600 / EXTRACTION_INTERVAL
+            }
+            Role::Trader | Role::Contractor => {
+                let margin = i64::max(earnings.trade_margin, earnings.contract_margin);
+                cargo_capacity(ship) as i64 * margin * 3600 / TRIP_DURATION
+            }
+            Role::Hauler | Role::Surveyor | Role::Probe | Role::Idle => 0,
+        }
+    }
+
+    fn role_count(fleet: &[Ship], role: Role) -> usize {
+        fleet
+            .iter()
+            .filter(|s| Role::assign(s, false) == role)
+            .count()
+    }
+}
+
+impl PurchasePolicy for RoiPolicy {
+    fn choose(
+        &self,
+        credits: i64,
+        fleet: &[Ship],
+        offers: &[ShipOffer],
+        earnings: &Earnings,
+    ) -> Option<ShipOffer> {
+        if fleet.len() >= self.max_ships {
+            return None;
+        }
+
+        let credit_reserve = self.strategy.settings().credit_reserve;
+        offers
+            .iter()
+            .filter(|o| o.price() + credit_reserve <= credits)
+            .filter(|o| {
+                let role = Role::for_offer(&o.ship);
+                self.role_caps
+                    .get(&role)
+                    .is_some_and(|&cap| Self::role_count(fleet, role) < cap)
+            })
+            .map(|o| (o, self.estimate_hourly_income(&o.ship, earnings)))
+            .filter(|(o, income)| *income > 0 && o.price() <= income * self.max_payback_hours)
+            .max_by(|(a, a_income), (b, b_income)| {
+                // Compare income/price ratios without going through floats.
+                (a_income * b.price()).cmp(&(b_income * a.price()))
+            })
+            .map(|(o, _)| o.clone())
+    }
+}
+
+/// Periodically checks our credits and buys whatever the policy recommends.
+pub struct Buyer {
+    log_context: String,
+    client: Client,
+    manager: Manager,
+    policy: Box<dyn PurchasePolicy>,
+}
+
+impl Buyer {
+    pub fn new(factory: &ManagerFactory, policy: Box<dyn PurchasePolicy>) -> Self {
+        let manager = factory.get("BUYER");
+        Self {
+            log_context: "BUYER".to_owned(),
+            client: manager.client().clone(),
+            manager,
+            policy,
+        }
+    }
+
+    /// What our ships earn, from the ledger, and could earn, from the system's known market
+    /// prices and the contracts on offer.
+    async fn earnings(&self, system_symbol: &str) -> Earnings {
+        let markets = self.manager.markets();
+        let contract_margin = (self.client.get_contracts().await.iter())
+            .filter(|c| !c.fulfilled)
+            .filter_map(|c| contract_margin(c, |s| markets.cheapest_price(system_symbol, s)))
+            .max();
+
+        Earnings {
+            observed: hourly_profit_by_role(&self.client.database().ledger(), Utc::now()),
+            trade_margin: (markets.best_route(system_symbol, 1)).map_or(0, |r| r.profit()),
+            contract_margin: contract_margin.unwrap_or(0),
+            ore_price: markets.average_sell_price(system_symbol, ORES),
+        }
+    }
+
+    /// Hands every ship bought over to `new_ships`, until shut down.
+    pub async fn run(
+        &self,
+        system_symbol: &str,
+        new_ships: mpsc::Sender<Ship>,
+        mut shutdown: Shutdown,
+    ) {
+        let context = &self.log_context;
+        info!("[{context}] Init manager done");
+
+        let mut stream = interval(Duration::from_secs(600));
+        loop {
+            tokio::select! {
+                _ = stream.tick() => {}
+                _ = shutdown.requested() => return,
+            }
+
+            info!("[{context}] Checking for funds");
+
+            let fleet = self.client.get_my_ships().await;
+            let credits = self.client.get_my_agent().await.credits;
+            self.manager.refresh_shipyards(system_symbol).await;
+            let offers = self.manager.shipyards().offers(system_symbol);
+            let earnings = self.earnings(system_symbol).await;
+
+            match self.policy.choose(credits, &fleet, &offers, &earnings) {
+                Some(offer) => {
+                    info!(
+                        "[{context}] Buying {} at {} for {} credits (credits={credits})",
+                        offer.ship.r#type.to_string(),
+                        offer.waypoint_symbol,
+                        offer.price()
+                    );
+                    // The manager logs why when it doesn't buy one
+                    if let Some(ship) = self
+                        .manager
+                        .purchase_ship(system_symbol, offer.ship.r#type)
+                        .await
+                    {
+                        info!("[{context}] Purchased ship {}", ship.symbol);
+                        if new_ships.send(*ship).await.is_err() {
+                            info!("[{context}] Nobody takes new ships anymore, stopping");
+                            return;
+                        }
+                    }
+                }
+                None => info!(
+                    "[{context}] Nothing worth buying among {} offers (credits={credits}, ships={})",
+                    offers.len(),
+                    fleet.len()
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::StrategySettings;
+    use spacedust::models::{
+        ship_engine, ship_frame, ship_module, ship_reactor, ShipEngine, ShipFrame, ShipModule,
+        ShipMount, ShipReactor, ShipRequirements, ShipyardShipCrew, SupplyLevel,
+    };
+
+    fn offer(ship_type: ShipType, price: i32, laser_strength: i32) -> ShipOffer {
+        let mut laser = ShipMount::new(
+            ship_mount::Symbol::MiningLaserI,
+            "Mining Laser I".into(),
+            ShipRequirements::new(),
+        );
+        laser.strength = Some(laser_strength);
+
+        ShipOffer {
+            waypoint_symbol: "X1-ZA40-A2".into(),
+            ship: ShipyardShip::new(
+                ship_type,
+                ship_type.to_string(),
+                String::new(),
+                SupplyLevel::Moderate,
+                price,
+                ShipFrame::new(
+                    ship_frame::Symbol::Miner,
+                    String::new(),
+                    String::new(),
+                    2,
+                    2,
+                    100,
+                    ShipRequirements::new(),
+                ),
+                ShipReactor::new(
+                    ship_reactor::Symbol::FissionI,
+                    String::new(),
+                    String::new(),
+                    31,
+                    ShipRequirements::new(),
+                ),
+                ShipEngine::new(
+                    ship_engine::Symbol::ImpulseDriveI,
+                    String::new(),
+                    String::new(),
+                    10,
+                    ShipRequirements::new(),
+                ),
+                vec![],
+                if laser_strength > 0 {
+                    vec![laser]
+                } else {
+                    vec![]
+                },
+                ShipyardShipCrew::new(0, 0),
+            ),
+        }
+    }
+
+    fn policy() -> RoiPolicy {
+        RoiPolicy {
+            strategy: Strategy::new(StrategySettings {
+                credit_reserve: 10_000,
+                sell_floor: 0,
+            }),
+            max_ships: 10,
+            role_caps: HashMap::from([(Role::Miner, 10), (Role::Trader, 5)]),
+            max_payback_hours: 48,
+            average_ore_price: 40,
+        }
+    }
+
+    #[test]
+    fn picks_best_return_on_investment() {
+        let offers = vec![
+            offer(ShipType::OreHound, 160_000, 10),
+            offer(ShipType::MiningDrone, 40_000, 5),
+        ];
+
+        let choice = policy()
+            .choose(500_000, &[], &offers, &Earnings::default())
+            .unwrap();
+        assert_eq!(choice.ship.r#type, ShipType::MiningDrone);
+    }
+
+    #[test]
+    fn keeps_credit_reserve() {
+        let offers = vec![offer(ShipType::MiningDrone, 40_000, 5)];
+
+        assert!(policy()
+            .choose(45_000, &[], &offers, &Earnings::default())
+            .is_none());
+        assert!(policy()
+            .choose(50_000, &[], &offers, &Earnings::default())
+            .is_some());
+    }
+
+    #[test]
+    fn ignores_ships_that_cannot_earn() {
+        let offers = vec![offer(ShipType::Probe, 20_000, 0)];
+
+        assert!(policy()
+            .choose(500_000, &[], &offers, &Earnings::default())
+            .is_none());
+    }
+
+    #[test]
+    fn traders_earn_from_the_best_margin() {
+        let mut frigate = offer(ShipType::CommandFrigate, 60_000, 0);
+        frigate.ship.frame.symbol = ship_frame::Symbol::Frigate;
+        let mut hold = ShipModule::new(
+            ship_module::Symbol::CargoHoldI,
+            "Cargo Hold".into(),
+            String::new(),
+            ShipRequirements::new(),
+        );
+        hold.capacity = Some(40);
+        frigate.ship.modules.push(hold);
+        let offers = vec![frigate];
+
+        let earnings = Earnings {
+            trade_margin: 5,
+            contract_margin: 20,
+            ..Earnings::default()
+        };
+        assert_eq!(
+            policy().estimate_hourly_income(&offers[0].ship, &earnings),
+            3200
+        );
+        assert!(policy().choose(500_000, &[], &offers, &earnings).is_some());
+        assert!(policy()
+            .choose(500_000, &[], &offers, &Earnings::default())
+            .is_none());
+    }
+
+    #[test]
+    fn observed_earnings_come_first() {
+        let offers = vec![offer(ShipType::MiningDrone, 40_000, 5)];
+        let earnings = Earnings {
+            observed: HashMap::from([(Role::Miner, 100)]),
+            ..Earnings::default()
+        };
+
+        assert_eq!(
+            policy().estimate_hourly_income(&offers[0].ship, &earnings),
+            100
+        );
+        assert!(policy().choose(500_000, &[], &offers, &earnings).is_none());
+    }
+
+    #[test]
+    fn roles_without_a_cap_are_not_bought() {
+        let mut policy = policy();
+        policy.role_caps.remove(&Role::Miner);
+        let offers = vec![offer(ShipType::MiningDrone, 40_000, 5)];
+
+        assert!(policy
+            .choose(500_000, &[], &offers, &Earnings::default())
+            .is_none());
+    }
+
+    #[test]
+    fn contracts_pay_over_the_cost_of_their_goods() {
+        let contract: Contract = serde_json::from_str("{\"id\":\"c1\",\"factionSymbol\":\"COSMIC\",\"type\":\"PROCUREMENT\",\"terms\":{\"deadline\":\"2024-01-27T00:00:00Z\",\"payment\":{\"onAccepted\":1000,\"onFulfilled\":5000},\"deliver\":[{\"tradeSymbol\":\"IRON_ORE\",\"destinationSymbol\":\"X1-ZA40-A1\",\"unitsRequired\":100,\"unitsFulfilled\":0}]},\"accepted\":true,\"fulfilled\":false,\"expiration\":\"2024-01-21T00:00:00Z\"}").unwrap();
+
+        assert_eq!(contract_margin(&contract, |_| Some(30)), Some(20));
+        assert_eq!(contract_margin(&contract, |_| None), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use chrono::Utc;
+use log::info;
+use spacedust::models::GetStatus200Response;
+
+use crate::{
+    accounts::{Account, Session},
+    alerts::Alerts,
+    client::{parse_time, Client},
+    credentials::Credentials,
+    setup::{parse_faction, Setup},
+    shutdown::{Shutdown, Stop},
+    states::StateStore,
+};
+
+/// How often the server status is polled when no reset is due soon.
+const POLL_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Watches for the server resetting, which invalidates our token and wipes the universe. When it
+/// does, the agent is stopped, then the finished run's data archived and the agent registered
+/// again to be played on the new account.
+pub struct ResetWatcher {
+    account: Account,
+    alerts: Alerts,
+    client: Client,
+    states: StateStore,
+    reset_date: String,
+}
+
+impl ResetWatcher {
+    pub fn new(session: &Session, states: StateStore, reset_date: &str) -> Self {
+        Self {
+            account: session.account.clone(),
+            alerts: session.alerts.clone(),
+            client: Client::new("RESET".into(), session),
+            states,
+            reset_date: reset_date.to_owned(),
+        }
+    }
+
+    /// Polls the server status until shut down, asking the agent to stop on a reset.
+    pub async fn run(&self, stop: Stop, mut shutdown: Shutdown) {
+        loop {
+            let status = self.client.get_status().await;
+            self.alerts.announcements(&status);
+            if shutdown.is_requested() {
+                return;
+            }
+            if self.has_reset(&status).await {
+                stop.request();
+                return;
+            }
+
+            // Check again shortly after the next reset if it's due before the next poll
+            let next_reset = parse_time(&status.server_resets.next) - Utc::now();
+            let wait = next_reset
+                .to_std()
+                .map(|d| d + Duration::from_secs(60))
+                .unwrap_or(POLL_INTERVAL)
+                .min(POLL_INTERVAL);
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = shutdown.requested() => return,
+            }
+        }
+    }
+
+    /// Whether the server reset since the run started, going by its status and our token.
+    pub async fn has_reset(&self, status: &GetStatus200Response) -> bool {
+        if status.reset_date == self.reset_date && self.client.token_valid().await {
+            return false;
+        }
+
+        info!(
+            "[RESET] Server reset on {}, last run started {}",
+            status.reset_date, self.reset_date
+        );
+        true
+    }
+
+    /// Archives the finished run and registers the agent again. The agent must be stopped.
+    pub async fn start_over(&self) {
+        let credentials = Credentials::load(&self.account.credentials_path());
+        let agent_symbol = (self.account.agent_symbol())
+            .or(credentials.as_ref().map(|c| c.agent_symbol.to_owned()))
+            .expect("AGENT_SYMBOL must be set to register again after a reset");
+
+        let archive = PathBuf::from("runs")
+            .join(self.reset_date.replace(':', "-"))
+            .join(&self.account.name);
+        if let Err(e) = fs::create_dir_all(&archive) {
+            info!("[RESET] Failed to create {}: {e}", archive.display());
+        }
+        if let Err(e) = self.client.database().archive(&archive.join("universe.db")) {
+            info!("[RESET] Failed to archive the database: {e}");
+        }
+        self.states.archive(&archive);
+        self.client.events().archive(&archive);
+        info!("[RESET] Archived the last run in {}", archive.display());
+
+        let faction = (self.account.var("FACTION"))
+            .or(credentials.map(|c| c.faction))
+            .and_then(|f| parse_faction(&f));
+        Setup::setup_account(&self.account, &agent_symbol, faction).await;
+
+        info!("[RESET] Registered {agent_symbol} again");
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use spacedust::models::{
+    ship_frame, ship_module, ship_mount, Ship, ShipMount, ShipRole, ShipyardShip,
+};
+
+use crate::purchasing::role_for_type;
+
+/// What a ship spends its time doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Role {
+    Miner,
+    /// Surveys the asteroid field for the miners working it.
+    Surveyor,
+    /// Waits at the asteroid field for the miners' cargo and sells it at the best market.
+    Hauler,
+    Trader,
+    Contractor,
+    /// Visits markets and shipyards to keep our price indexes fresh.
+    Probe,
+    /// Nothing we automate suits the ship.
+    Idle,
+}
+
+/// Cargo space below which a ship isn't worth sending on trade runs or contract deliveries.
+const MIN_HAULING_CAPACITY: i32 = 30;
+
+impl Role {
+    /// Picks a role from the ship's registered role, frame, cargo hold and mounts.
+    pub fn assign(ship: &Ship, contract_available: bool) -> Self {
+        let (can_mine, can_survey) = capabilities(&ship.mounts);
+
+        if let Some(role) = Self::from_registration(
+            ship.registration.role,
+            ship.cargo.capacity,
+            can_mine,
+            can_survey,
+        ) {
+            return role;
+        }
+
+        Self::choose(
+            ship.frame.symbol,
+            ship.cargo.capacity,
+            can_mine,
+            contract_available,
+        )
+    }
+
+    /// The role a ship for sale would get once bought. Ships that could trade or take contracts
+    /// count as traders, the fleet switching them over when a contract comes up.
+    pub fn for_offer(ship: &ShipyardShip) -> Self {
+        let (can_mine, can_survey) = capabilities(&ship.mounts);
+        let capacity = cargo_capacity(ship);
+
+        Self::from_registration(role_for_type(ship.r#type), capacity, can_mine, can_survey)
+            .unwrap_or_else(|| Self::choose(ship.frame.symbol, capacity, can_mine, false))
+    }
+
+    /// The role implied by the ship's registration, if it is specialised enough to tell.
+    fn from_registration(
+        registration: ShipRole,
+        cargo_capacity: i32,
+        can_mine: bool,
+        can_survey: bool,
+    ) -> Option<Self> {
+        match registration {
+            ShipRole::Excavator if can_mine => Some(Role::Miner),
+            ShipRole::Surveyor if can_survey => Some(Role::Surveyor),
+            ShipRole::Hauler | ShipRole::Transport if cargo_capacity >= MIN_HAULING_CAPACITY => {
+                Some(Role::Hauler)
+            }
+            ShipRole::Satellite | ShipRole::Explorer => Some(Role::Probe),
+            ShipRole::Interceptor | ShipRole::Patrol | ShipRole::Repair => Some(Role::Idle),
+            _ => None,
+        }
+    }
+
+    fn choose(
+        frame: ship_frame::Symbol,
+        cargo_capacity: i32,
+        can_mine: bool,
+        contract_available: bool,
+    ) -> Self {
+        let is_mining_frame =
+            matches!(frame, ship_frame::Symbol::Miner | ship_frame::Symbol::Drone);
+
+        if cargo_capacity == 0 || frame == ship_frame::Symbol::Probe {
+            Role::Probe
+        } else if can_mine && is_mining_frame {
+            Role::Miner
+        } else if cargo_capacity >= MIN_HAULING_CAPACITY && contract_available {
+            Role::Contractor
+        } else if cargo_capacity >= MIN_HAULING_CAPACITY {
+            Role::Trader
+        } else if can_mine {
+            Role::Miner
+        } else {
+            Role::Probe
+        }
+    }
+}
+
+/// Whether the mounts can mine, and whether they can survey.
+fn capabilities(mounts: &[ShipMount]) -> (bool, bool) {
+    let can_mine = mounts.iter().any(|m| {
+        matches!(
+            m.symbol,
+            ship_mount::Symbol::MiningLaserI
+                | ship_mount::Symbol::MiningLaserIi
+                | ship_mount::Symbol::MiningLaserIii
+        )
+    });
+    let can_survey = mounts.iter().any(|m| {
+        matches!(
+            m.symbol,
+            ship_mount::Symbol::SurveyorI
+                | ship_mount::Symbol::SurveyorIi
+                | ship_mount::Symbol::SurveyorIii
+        )
+    });
+    (can_mine, can_survey)
+}
+
+/// The cargo space the ship for sale comes with.
+pub fn cargo_capacity(ship: &ShipyardShip) -> i32 {
+    ship.modules
+        .iter()
+        .filter(|m| {
+            matches!(
+                m.symbol,
+                ship_module::Symbol::CargoHoldI
+                    | ship_module::Symbol::CargoHoldIi
+                    | ship_module::Symbol::CargoHoldIii
+            )
+        })
+        .filter_map(|m| m.capacity)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_frigate_takes_contracts_then_trades() {
+        assert_eq!(
+            Role::choose(ship_frame::Symbol::Frigate, 40, true, true),
+            Role::Contractor
+        );
+        assert_eq!(
+            Role::choose(ship_frame::Symbol::Frigate, 40, true, false),
+            Role::Trader
+        );
+    }
+
+    #[test]
+    fn mining_frames_mine() {
+        assert_eq!(
+            Role::choose(ship_frame::Symbol::Miner, 60, true, true),
+            Role::Miner
+        );
+        assert_eq!(
+            Role::choose(ship_frame::Symbol::Probe, 0, false, true),
+            Role::Probe
+        );
+    }
+
+    #[test]
+    fn registration_decides_specialised_ships() {
+        assert_eq!(
+            Role::from_registration(ShipRole::Hauler, 80, false, false),
+            Some(Role::Hauler)
+        );
+        assert_eq!(
+            Role::from_registration(ShipRole::Surveyor, 0, false, true),
+            Some(Role::Surveyor)
+        );
+        assert_eq!(
+            Role::from_registration(ShipRole::Surveyor, 0, false, false),
+            None
+        );
+        assert_eq!(
+            Role::from_registration(ShipRole::Command, 40, true, false),
+            None
+        );
+    }
+}
@@ -0,0 +1,667 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::info;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::interval,
+};
+
+use spacedust::models::Ship;
+
+use crate::{
+    behaviours::SavedBehaviour,
+    manager::{Manager, ManagerFactory},
+    metrics,
+    shutdown::Shutdown,
+    states::StateStore,
+    supervisor::{backoff, panic_message},
+};
+
+/// Why a ship is waiting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Arrival,
+    Cooldown,
+    MarketRefresh,
+    Ready,
+}
+
+/// What a behaviour wants once a step is done.
+pub enum Next {
+    Wake { at: DateTime<Utc>, reason: Reason },
+    Done,
+}
+
+impl Next {
+    pub fn now() -> Self {
+        Next::Wake {
+            at: Utc::now(),
+            reason: Reason::Ready,
+        }
+    }
+
+    pub fn after(seconds: u64, reason: Reason) -> Self {
+        Next::Wake {
+            at: Utc::now() + chrono::Duration::seconds(seconds as i64),
+            reason,
+        }
+    }
+}
+
+/// A ship behaviour written as a state machine: each step does a bounded amount of work and
+/// tells the scheduler when to run the next one, instead of sleeping itself.
+#[async_trait]
+pub trait Behaviour: Send {
+    /// What the ship is doing, for inspection.
+    fn describe(&self) -> String;
+
+    /// The kind of behaviour, without its state, to label metrics with.
+    fn name(&self) -> String;
+
+    /// The state to persist after each step, None if the behaviour can't be resumed.
+    fn save(&self) -> Option<SavedBehaviour>;
+
+    /// Adjusts a restored state to where the ship actually is and what it holds.
+    fn reconcile(&mut self, _ship: &Ship) {}
+
+    async fn step(&mut self, manager: &Manager, ship_symbol: &str) -> Next;
+}
+
+/// Hashed timer wheel with one slot per tick. Items further away than the wheel's length wait
+/// in their slot for as many turns as needed.
+pub struct TimerWheel<T> {
+    slots: Vec<Vec<(u64, T)>>,
+    tick: u64,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            slots: (0..slots).map(|_| Vec::new()).collect(),
+            tick: 0,
+        }
+    }
+
+    /// Schedules the item to come out in `delay` ticks, at least one.
+    pub fn insert(&mut self, delay: u64, item: T) {
+        let deadline = self.tick + delay.max(1);
+        let slot = (deadline % self.slots.len() as u64) as usize;
+        self.slots[slot].push((deadline, item));
+    }
+
+    /// Moves to the next tick, returning the items due.
+    pub fn advance(&mut self) -> Vec<T> {
+        self.tick += 1;
+        let slot = (self.tick % self.slots.len() as u64) as usize;
+
+        let (due, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.slots[slot])
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= self.tick);
+        self.slots[slot] = later;
+
+        due.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+/// Ticks are one second, so this covers waits of up to ~8 minutes in a single turn.
+const WHEEL_SLOTS: usize = 512;
+
+/// Snapshot of a ship's place in the scheduler.
+#[derive(Clone, Debug)]
+pub struct ShipStatus {
+    pub ship_symbol: String,
+    pub behaviour: Option<String>,
+    /// The behaviour's name, which unlike its description doesn't change from step to step.
+    pub name: Option<String>,
+    pub queued: usize,
+    pub running: bool,
+    pub paused: bool,
+    pub wake: Option<(DateTime<Utc>, Reason)>,
+}
+
+impl Display for ShipStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.ship_symbol,
+            self.behaviour.as_deref().unwrap_or("nothing")
+        )?;
+
+        if self.paused {
+            write!(f, ", paused")?;
+        }
+        if self.running {
+            write!(f, ", running")?;
+        } else if let Some((at, reason)) = self.wake {
+            write!(f, ", waking at {at} for {reason:?}")?;
+        }
+        if self.queued > 0 {
+            write!(f, ", {} jobs queued", self.queued)?;
+        }
+
+        Ok(())
+    }
+}
+
+enum Command {
+    Assign(String, Box<dyn Behaviour>),
+    Interrupt(String, Box<dyn Behaviour>),
+    Enqueue(String, Box<dyn Behaviour>),
+    Pause(String),
+    Resume(String),
+    Idle(String, bool),
+    Inspect(oneshot::Sender<Vec<ShipStatus>>),
+    Stop(oneshot::Sender<()>),
+}
+
+/// Cheap handle to talk to the scheduler from other tasks.
+#[derive(Clone)]
+pub struct SchedulerHandle {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl SchedulerHandle {
+    /// Pre-empts the ship: the behaviour replaces whatever it is doing as soon as its current
+    /// step ends, and its queued jobs are dropped.
+    pub fn assign(&self, ship_symbol: &str, behaviour: Box<dyn Behaviour>) {
+        self.send(Command::Assign(ship_symbol.to_owned(), behaviour));
+    }
+
+    /// Runs the behaviour as soon as the ship's current step ends, then resumes what the ship was
+    /// doing from where it was.
+    pub fn interrupt(&self, ship_symbol: &str, behaviour: Box<dyn Behaviour>) {
+        self.send(Command::Interrupt(ship_symbol.to_owned(), behaviour));
+    }
+
+    /// Runs the behaviour once everything the ship has to do before is done.
+    pub fn enqueue(&self, ship_symbol: &str, behaviour: Box<dyn Behaviour>) {
+        self.send(Command::Enqueue(ship_symbol.to_owned(), behaviour));
+    }
+
+    /// Stops running the ship's steps until resumed. A step already running finishes.
+    pub fn pause(&self, ship_symbol: &str) {
+        self.send(Command::Pause(ship_symbol.to_owned()));
+    }
+
+    pub fn resume(&self, ship_symbol: &str) {
+        self.send(Command::Resume(ship_symbol.to_owned()));
+    }
+
+    /// Holds the ship's steps while it has nothing to do, like pausing it, but without touching
+    /// a pause asked for by the operator.
+    pub fn set_idle(&self, ship_symbol: &str, idle: bool) {
+        self.send(Command::Idle(ship_symbol.to_owned(), idle));
+    }
+
+    pub async fn inspect(&self) -> Vec<ShipStatus> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(Command::Inspect(sender));
+        receiver.await.unwrap()
+    }
+
+    /// Stops starting steps, returning once the steps running are done and their ships' states
+    /// saved.
+    pub async fn stop(&self) {
+        let (sender, receiver) = oneshot::channel();
+        self.send(Command::Stop(sender));
+        receiver.await.ok();
+    }
+
+    fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            info!("[SCHEDULER] Scheduler stopped, dropping command");
+        }
+    }
+}
+
+struct ShipSlot {
+    manager: Manager,
+    /// None while a step runs, the behaviour then lives in the step's task.
+    current: Option<Box<dyn Behaviour>>,
+    description: Option<String>,
+    name: Option<String>,
+    queue: VecDeque<Box<dyn Behaviour>>,
+    running: bool,
+    /// Paused by the operator.
+    paused: bool,
+    /// Paused for having nothing to do, until the ship gets another role.
+    idle: bool,
+    /// Set when a new behaviour was assigned while a step was running.
+    preempted: bool,
+    /// Set when the pre-empting behaviour was an interruption, to resume the current one after.
+    interrupted: bool,
+    /// Bumped whenever the ship is rescheduled, so stale timers are ignored.
+    generation: u64,
+    /// Steps that panicked in a row, to back off restarting the behaviour.
+    failures: u32,
+    /// Set after a panic, so the next step first reconciles with the ship as the API has it.
+    resync: bool,
+    /// The behaviour's state when its running step started, to restart from if it panics.
+    started_from: Option<SavedBehaviour>,
+    wake: Option<(DateTime<Utc>, Reason)>,
+}
+
+impl ShipSlot {
+    /// Whether the ship's steps are held, by the operator or for being idle.
+    fn held(&self) -> bool {
+        self.paused || self.idle
+    }
+
+    fn status(&self, ship_symbol: &str) -> ShipStatus {
+        ShipStatus {
+            ship_symbol: ship_symbol.to_owned(),
+            behaviour: self.description.clone(),
+            name: self.name.clone(),
+            queued: self.queue.len(),
+            running: self.running,
+            paused: self.held(),
+            wake: self.wake,
+        }
+    }
+}
+
+/// The result of a step, or what it panicked with.
+type Completion = (String, Result<(Box<dyn Behaviour>, Next), String>);
+
+/// Runs every ship's behaviour from a single timer wheel, so all the fleet's waiting happens in
+/// one place where it can be inspected, paused or pre-empted.
+pub struct Scheduler {
+    factory: ManagerFactory,
+    states: StateStore,
+    ships: HashMap<String, ShipSlot>,
+    timers: TimerWheel<(String, u64)>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    completions: mpsc::UnboundedReceiver<Completion>,
+    completions_sender: mpsc::UnboundedSender<Completion>,
+    /// Set once asked to stop, no step is started after.
+    stopping: bool,
+    /// Who to tell once the last running step is done.
+    stopped: Vec<oneshot::Sender<()>>,
+}
+
+impl Scheduler {
+    pub fn new(factory: ManagerFactory, states: StateStore) -> (Self, SchedulerHandle) {
+        let (commands_sender, commands) = mpsc::unbounded_channel();
+        let (completions_sender, completions) = mpsc::unbounded_channel();
+
+        let scheduler = Self {
+            factory,
+            states,
+            ships: HashMap::new(),
+            timers: TimerWheel::new(WHEEL_SLOTS),
+            commands,
+            completions,
+            completions_sender,
+            stopping: false,
+            stopped: Vec::new(),
+        };
+
+        (
+            scheduler,
+            SchedulerHandle {
+                commands: commands_sender,
+            },
+        )
+    }
+
+    /// Runs the ships' steps until stopped, with the handle or by the shutdown.
+    pub async fn run(mut self, mut shutdown: Shutdown) {
+        let mut ticks = interval(Duration::from_secs(1));
+
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {
+                    for (ship_symbol, generation) in self.timers.advance() {
+                        self.wake(&ship_symbol, generation);
+                    }
+                }
+                Some(command) = self.commands.recv() => self.handle(command),
+                Some((ship_symbol, result)) = self.completions.recv() => {
+                    self.complete(&ship_symbol, result)
+                }
+                _ = shutdown.requested(), if !self.stopping => self.stop(),
+            }
+
+            if self.stopping && !self.ships.values().any(|s| s.running) {
+                info!("[SCHEDULER] Every step is done, stopping");
+                for reply in self.stopped.drain(..) {
+                    reply.send(()).ok();
+                }
+                return;
+            }
+        }
+    }
+
+    fn slot(&mut self, ship_symbol: &str) -> &mut ShipSlot {
+        let factory = &self.factory;
+        self.ships
+            .entry(ship_symbol.to_owned())
+            .or_insert_with(|| ShipSlot {
+                manager: factory.get(ship_symbol),
+                current: None,
+                description: None,
+                name: None,
+                queue: VecDeque::new(),
+                running: false,
+                paused: false,
+                idle: false,
+                preempted: false,
+                interrupted: false,
+                generation: 0,
+                failures: 0,
+                resync: false,
+                started_from: None,
+                wake: None,
+            })
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Assign(ship_symbol, behaviour) => {
+                info!(
+                    "[SCHEDULER] Assigning {} to {ship_symbol}",
+                    behaviour.describe()
+                );
+                let slot = self.slot(&ship_symbol);
+                slot.queue.clear();
+                slot.queue.push_front(behaviour);
+
+                if slot.running {
+                    slot.preempted = true;
+                    slot.interrupted = false;
+                } else {
+                    slot.current = None;
+                    self.start_next(&ship_symbol);
+                }
+            }
+            Command::Interrupt(ship_symbol, behaviour) => {
+                info!(
+                    "[SCHEDULER] Interrupting {ship_symbol} with {}",
+                    behaviour.describe()
+                );
+                let slot = self.slot(&ship_symbol);
+
+                if slot.running {
+                    slot.queue.push_front(behaviour);
+                    slot.preempted = true;
+                    slot.interrupted = true;
+                } else {
+                    if let Some(current) = slot.current.take() {
+                        slot.queue.push_front(current);
+                    }
+                    slot.queue.push_front(behaviour);
+                    self.start_next(&ship_symbol);
+                }
+            }
+            Command::Enqueue(ship_symbol, behaviour) => {
+                info!(
+                    "[SCHEDULER] Queueing {} for {ship_symbol}",
+                    behaviour.describe()
+                );
+                let slot = self.slot(&ship_symbol);
+                slot.queue.push_back(behaviour);
+
+                if !slot.running && slot.current.is_none() {
+                    self.start_next(&ship_symbol);
+                }
+            }
+            Command::Pause(ship_symbol) => {
+                info!("[SCHEDULER] Pausing {ship_symbol}");
+                self.slot(&ship_symbol).paused = true;
+                self.record(&ship_symbol);
+            }
+            Command::Resume(ship_symbol) => {
+                let slot = self.slot(&ship_symbol);
+                if !slot.paused {
+                    return;
+                }
+
+                info!("[SCHEDULER] Resuming {ship_symbol}");
+                slot.paused = false;
+                self.release(&ship_symbol);
+            }
+            Command::Idle(ship_symbol, idle) => {
+                let slot = self.slot(&ship_symbol);
+                if slot.idle == idle {
+                    return;
+                }
+
+                slot.idle = idle;
+                if idle {
+                    info!("[SCHEDULER] Holding idle {ship_symbol}");
+                    self.record(&ship_symbol);
+                } else {
+                    self.release(&ship_symbol);
+                }
+            }
+            Command::Inspect(reply) => {
+                let mut statuses: Vec<_> = self
+                    .ships
+                    .iter()
+                    .map(|(ship_symbol, slot)| slot.status(ship_symbol))
+                    .collect();
+                statuses.sort_by(|a, b| a.ship_symbol.cmp(&b.ship_symbol));
+                reply.send(statuses).ok();
+            }
+            Command::Stop(reply) => {
+                self.stopped.push(reply);
+                if !self.stopping {
+                    self.stop();
+                }
+            }
+        }
+    }
+
+    /// Schedules the ship's behaviour again once nothing holds it anymore.
+    fn release(&mut self, ship_symbol: &str) {
+        let slot = self.slot(ship_symbol);
+        if slot.held() {
+            self.record(ship_symbol);
+            return;
+        }
+
+        if !slot.running && slot.current.is_some() {
+            let (at, reason) = slot.wake.unwrap_or((Utc::now(), Reason::Ready));
+            self.schedule(ship_symbol, at, reason);
+        }
+    }
+
+    fn stop(&mut self) {
+        let running = self.ships.values().filter(|s| s.running).count();
+        info!("[SCHEDULER] Stopping, waiting for {running} steps to finish");
+        self.stopping = true;
+    }
+
+    fn wake(&mut self, ship_symbol: &str, generation: u64) {
+        let Some(slot) = self.ships.get_mut(ship_symbol) else {
+            return;
+        };
+        if slot.generation != generation || slot.running || slot.held() {
+            return;
+        }
+        if self.stopping {
+            return;
+        }
+        let Some(mut behaviour) = slot.current.take() else {
+            return;
+        };
+
+        slot.running = true;
+        slot.started_from = behaviour.save();
+        let resync = std::mem::take(&mut slot.resync);
+        let manager = slot.manager.clone();
+        let completions = self.completions_sender.clone();
+        self.record(ship_symbol);
+        let ship_symbol = ship_symbol.to_owned();
+
+        tokio::spawn(async move {
+            let symbol = ship_symbol.clone();
+            let step = tokio::spawn(async move {
+                if resync {
+                    let ship = manager.client().get_ship(&symbol).await;
+                    behaviour.reconcile(&ship);
+                }
+                let next = behaviour.step(&manager, &symbol).await;
+                (behaviour, next)
+            });
+
+            completions
+                .send((ship_symbol, step.await.map_err(panic_message)))
+                .ok();
+        });
+    }
+
+    fn complete(&mut self, ship_symbol: &str, result: Result<(Box<dyn Behaviour>, Next), String>) {
+        let slot = self.slot(ship_symbol);
+        slot.running = false;
+
+        let (behaviour, next) = match result {
+            Result::Ok(result) => {
+                slot.failures = 0;
+                result
+            }
+            Result::Err(message) => {
+                self.restart(ship_symbol, &message);
+                return;
+            }
+        };
+
+        if slot.preempted {
+            slot.preempted = false;
+            if std::mem::take(&mut slot.interrupted) && matches!(next, Next::Wake { .. }) {
+                // Comes back once the interruption is done
+                let at = slot.queue.len().min(1);
+                slot.queue.insert(at, behaviour);
+            }
+            self.start_next(ship_symbol);
+            return;
+        }
+
+        // Behaviours that can't be saved, like orders, leave the state of the one they interrupt
+        let saved = behaviour.save();
+        match next {
+            Next::Done => {
+                if saved.is_some() {
+                    self.states.remove(ship_symbol);
+                }
+                self.start_next(ship_symbol)
+            }
+            Next::Wake { at, reason } => {
+                if let Some(state) = saved {
+                    self.states.save(ship_symbol, state);
+                }
+
+                let slot = self.slot(ship_symbol);
+                slot.description = Some(behaviour.describe());
+                slot.name = Some(behaviour.name());
+                slot.current = Some(behaviour);
+                self.schedule(ship_symbol, at, reason);
+            }
+        }
+    }
+
+    /// Restarts the behaviour whose step panicked from its last saved state, after a backoff and
+    /// once the ship is synced from the API again. Behaviours that can't be saved are dropped.
+    fn restart(&mut self, ship_symbol: &str, message: &str) {
+        let slot = self.slot(ship_symbol);
+        let saved = slot.started_from.take();
+        slot.failures += 1;
+        let failures = slot.failures;
+        let description = slot.description.clone().unwrap_or("nothing".to_owned());
+
+        if slot.preempted {
+            info!("[SCHEDULER] Step of {ship_symbol} panicked while {description}: {message}");
+            slot.preempted = false;
+            if std::mem::take(&mut slot.interrupted) {
+                if let Some(saved) = saved {
+                    let at = slot.queue.len().min(1);
+                    slot.queue.insert(at, saved.restore());
+                    slot.resync = true;
+                }
+            }
+            self.start_next(ship_symbol);
+            return;
+        }
+        let Some(saved) = saved else {
+            info!(
+                "[SCHEDULER] Step of {ship_symbol} panicked while {description}: {message}, dropping its behaviour"
+            );
+            self.start_next(ship_symbol);
+            return;
+        };
+
+        let delay = backoff(failures);
+        info!(
+            "[SCHEDULER] Step of {ship_symbol} panicked while {description}: {message}, restarting in {}s (failure {failures})",
+            delay.as_secs()
+        );
+        slot.current = Some(saved.restore());
+        slot.resync = true;
+        let at = Utc::now() + chrono::Duration::from_std(delay).unwrap();
+        self.schedule(ship_symbol, at, Reason::Ready);
+    }
+
+    /// Moves on to the ship's next queued job, if any, syncing it with where the ship is now.
+    fn start_next(&mut self, ship_symbol: &str) {
+        let slot = self.slot(ship_symbol);
+        slot.current = slot.queue.pop_front();
+        slot.resync = slot.current.is_some();
+        slot.description = slot.current.as_ref().map(|b| b.describe());
+        slot.name = slot.current.as_ref().map(|b| b.name());
+        slot.wake = None;
+
+        if slot.current.is_some() {
+            self.schedule(ship_symbol, Utc::now(), Reason::Ready);
+        } else {
+            self.record(ship_symbol);
+        }
+    }
+
+    fn schedule(&mut self, ship_symbol: &str, at: DateTime<Utc>, reason: Reason) {
+        let slot = self.slot(ship_symbol);
+        slot.generation += 1;
+        slot.wake = Some((at, reason));
+        let generation = slot.generation;
+
+        let delay = (at - Utc::now()).num_seconds().max(0) as u64;
+        self.timers
+            .insert(delay, (ship_symbol.to_owned(), generation));
+        self.record(ship_symbol);
+    }
+
+    /// Publishes the ship's status to the metrics.
+    fn record(&self, ship_symbol: &str) {
+        if let Some(slot) = self.ships.get(ship_symbol) {
+            metrics::record_ship_status(&slot.status(ship_symbol));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_wheel_releases_items_when_due() {
+        let mut wheel = TimerWheel::new(4);
+        wheel.insert(0, "now");
+        wheel.insert(2, "soon");
+        wheel.insert(6, "later");
+
+        assert_eq!(wheel.advance(), vec!["now"]);
+        // "later" sits in the same slot as "soon", a full turn further.
+        assert_eq!(wheel.advance(), vec!["soon"]);
+
+        assert!(wheel.advance().is_empty());
+        assert!(wheel.advance().is_empty());
+        assert!(wheel.advance().is_empty());
+        assert_eq!(wheel.advance(), vec!["later"]);
+    }
+}
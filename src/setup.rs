@@ -1,90 +1,294 @@
-use std::time::Duration;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use log::info;
+use serde::{Deserialize, Serialize};
 use spacedust::{
     apis::{
-        configuration::Configuration, contracts_api, default_api::register, fleet_api, systems_api,
+        configuration::Configuration,
+        default_api::{get_status, register},
+        factions_api,
     },
     models::{
-        NavigateShipRequest, PurchaseShipRequest, RegisterRequest, WaypointTraitSymbol,
-        WaypointType,
+        Faction, FactionSymbol, FactionTraitSymbol, RegisterRequest, Ship, ShipRole, ShipType,
+        WaypointTraitSymbol, WaypointType,
     },
 };
 
-use crate::configuration::ConfigurationFactory;
+use crate::{
+    accounts::Account, client::Client, configuration::ConfigurationFactory,
+    credentials::Credentials,
+};
+
+/// Faction traits that make for good trading and mining grounds.
+const PREFERRED_TRAITS: &[FactionTraitSymbol] = &[
+    FactionTraitSymbol::Capitalistic,
+    FactionTraitSymbol::Commercial,
+    FactionTraitSymbol::FreeMarkets,
+    FactionTraitSymbol::Entrepreneurial,
+    FactionTraitSymbol::Industrious,
+    FactionTraitSymbol::Resourceful,
+    FactionTraitSymbol::Welcoming,
+];
+
+/// Checks the symbol is one the server accepts: 3 to 14 letters, digits, dashes or underscores.
+/// Returns it upper-cased like the server does.
+pub fn validate_agent_symbol(symbol: &str) -> Result<String, String> {
+    if !(3..=14).contains(&symbol.len()) {
+        return Err(format!(
+            "Agent symbol {symbol} must be 3 to 14 characters long"
+        ));
+    }
+
+    match symbol
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_')
+    {
+        Some(c) => Err(format!("Agent symbol {symbol} can't contain '{c}'")),
+        None => Ok(symbol.to_ascii_uppercase()),
+    }
+}
+
+/// Parses a faction symbol like `COSMIC`.
+pub fn parse_faction(symbol: &str) -> Option<FactionSymbol> {
+    serde_json::from_value(serde_json::Value::String(symbol.to_ascii_uppercase())).ok()
+}
+
+/// The recruiting faction with the most preferred traits, the first one listed on ties.
+fn choose_faction(factions: &[Faction]) -> Option<FactionSymbol> {
+    let score = |f: &Faction| {
+        f.traits
+            .iter()
+            .filter(|t| PREFERRED_TRAITS.contains(&t.symbol))
+            .count()
+    };
+
+    factions
+        .iter()
+        .filter(|f| f.is_recruiting)
+        .rev()
+        .max_by_key(|f| score(f))
+        .map(|f| f.symbol)
+}
+
+/// Our command ship, the one every agent starts with.
+pub fn command_ship(ships: &[Ship]) -> Option<&Ship> {
+    ships
+        .iter()
+        .find(|s| s.registration.role == ShipRole::Command)
+}
+
+/// Credits kept aside when buying the first ships, for fuel and the first trades.
+const BOOTSTRAP_RESERVE: i64 = 10_000;
+
+/// Where setup stands. Saved after every step so a crash resumes it without redoing any.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+enum Step {
+    AcceptContracts,
+    PlanPurchases,
+    #[serde(rename_all = "camelCase")]
+    Buying {
+        shipyard: String,
+        fleet_size: i32,
+    },
+    Deploy,
+    Done,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Checkpoint {
+    agent_symbol: String,
+    reset_date: String,
+    step: Step,
+}
+
+impl Checkpoint {
+    fn path(account: &Account) -> PathBuf {
+        account.path("BOOTSTRAP_FILE", "bootstrap.json")
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    fn save(&self, path: &Path) {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    }
+}
+
+/// How many ships at the price the credits buy, keeping the reserve aside.
+fn affordable(credits: i64, price: i32) -> i32 {
+    if price <= 0 {
+        return 0;
+    }
+
+    ((credits - BOOTSTRAP_RESERVE).max(0) / price as i64) as i32
+}
+
+async fn sleep_until(arrival: DateTime<Utc>) {
+    let wait = (arrival - Utc::now()).num_seconds().max(0) as u64;
+
+    info!("[SETUP] Waiting {wait} seconds for ships to arrive");
+    tokio::time::sleep(Duration::from_secs(wait)).await;
+}
 
 pub struct Setup {}
 
 impl Setup {
-    pub async fn setup_account(username: &str) {
-        info!("[SETUP] Registering");
+    /// Runs setup when the account has no agent to play yet, or when its last setup didn't
+    /// finish. Registers its agent symbol with its `FACTION`, picking one when it isn't set.
+    pub async fn run_if_needed(account: &Account) {
+        let unfinished = Checkpoint::load(&Checkpoint::path(account))
+            .filter(|c| c.step != Step::Done)
+            .map(|c| c.agent_symbol);
+        let first_run = Credentials::load(&account.credentials_path()).is_none()
+            && account.var("TOKEN").is_none();
+        if unfinished.is_none() && !first_run {
+            return;
+        }
+
+        let agent_symbol = unfinished
+            .or_else(|| account.agent_symbol())
+            .expect("AGENT_SYMBOL must be set to register an agent");
+        let faction = account.var("FACTION").and_then(|f| parse_faction(&f));
+        Self::setup_account(account, &agent_symbol, faction).await;
+    }
+
+    /// Registers the agent, saving its credentials, buys as many ships as it can afford and
+    /// sends them to mine. Resumes from the last step completed when the agent is already
+    /// registered for this reset. Returns the credentials.
+    pub async fn setup_account(
+        account: &Account,
+        username: &str,
+        faction: Option<FactionSymbol>,
+    ) -> Credentials {
+        let username = validate_agent_symbol(username).unwrap();
+        let anonymous = &ConfigurationFactory::get_anonymous_config(&account.name);
+        let reset_date = get_status(anonymous).await.unwrap().reset_date;
+
+        // Saved before registering, so a crash once registered still resumes setup
+        let path = Checkpoint::path(account);
+        let mut checkpoint = Checkpoint::load(&path)
+            .filter(|c| c.agent_symbol == username && c.reset_date == reset_date)
+            .unwrap_or(Checkpoint {
+                agent_symbol: username.to_owned(),
+                reset_date: reset_date.to_owned(),
+                step: Step::AcceptContracts,
+            });
+        checkpoint.save(&path);
+
+        let credentials = match Credentials::load(&account.credentials_path())
+            .filter(|c| c.agent_symbol == username && c.reset_date == reset_date)
+        {
+            Some(credentials) => {
+                info!("[SETUP] {username} is already registered, resuming");
+                credentials
+            }
+            None => Self::register(anonymous, account, &username, faction, &reset_date).await,
+        };
+
+        let session = account.connect();
+        let client = &Client::new("SETUP".to_owned(), &session);
+        loop {
+            info!("[SETUP] Step: {:?}", checkpoint.step);
+            checkpoint.step = match &checkpoint.step {
+                Step::AcceptContracts => Self::accept_contracts(client).await,
+                Step::PlanPurchases => Self::plan_purchases(client).await,
+                Step::Buying {
+                    shipyard,
+                    fleet_size,
+                } => Self::buy_ships(client, shipyard, *fleet_size).await,
+                Step::Deploy => Self::deploy(client).await,
+                Step::Done => break,
+            };
+            checkpoint.save(&path);
+        }
+
+        info!("[SETUP] Ready to go!");
+        credentials
+    }
+
+    async fn register(
+        configuration: &Configuration,
+        account: &Account,
+        username: &str,
+        faction: Option<FactionSymbol>,
+        reset_date: &str,
+    ) -> Credentials {
+        let faction = match faction {
+            Some(f) => f,
+            None => Self::pick_faction(configuration).await,
+        };
+
+        info!(
+            "[SETUP] Registering {username} with faction {}",
+            faction.to_string()
+        );
         let agent = register(
-            &Configuration::new(),
-            Some(RegisterRequest::new(
-                spacedust::models::FactionSymbol::Cosmic,
-                username.to_string(),
-            )),
+            configuration,
+            Some(RegisterRequest::new(faction, username.to_owned())),
         )
         .await
         .unwrap();
 
-        let token = agent.data.token.as_str();
-        info!("[SETUP] TOKEN={token}");
-
-        let configuration = &ConfigurationFactory::get_config(agent.data.token.as_str());
+        let credentials = Credentials {
+            token: agent.data.token,
+            agent_symbol: agent.data.agent.symbol,
+            faction: agent.data.agent.starting_faction,
+            headquarters: agent.data.agent.headquarters,
+            reset_date: reset_date.to_owned(),
+        };
+        credentials.save(&account.credentials_path());
+        credentials
+    }
 
-        let contracts: Vec<_> = contracts_api::get_contracts(configuration, None, None)
+    async fn pick_faction(configuration: &Configuration) -> FactionSymbol {
+        let factions = factions_api::get_factions(configuration, None, Some(20))
             .await
             .unwrap()
             .data;
+
+        let faction = choose_faction(&factions).expect("No faction is recruiting");
+        info!("[SETUP] Picked faction {}", faction.to_string());
+        faction
+    }
+
+    async fn accept_contracts(client: &Client) -> Step {
+        let contracts = client.get_contracts().await;
         info!("[SETUP] Found {} contracts", contracts.len());
-        for contract in contracts.iter() {
+
+        for contract in contracts.iter().filter(|c| !c.accepted) {
             info!(
-                "[SETUP] Contract {}: {}, {}, {}",
+                "[SETUP] Accepting contract {}: {}, {}, {}",
                 contract.id,
                 contract.terms.deadline,
                 contract.terms.payment.on_accepted,
                 contract.terms.payment.on_fulfilled
-            )
-        }
-
-        for contract in contracts {
-            info!("[SETUP] Accepting contract {}", contract.id);
-            contracts_api::accept_contract(configuration, contract.id.as_str())
-                .await
-                .unwrap();
+            );
+            if let Result::Err(e) = client.accept_contract(&contract.id).await {
+                info!("[SETUP] Failed to accept contract {}: {e}", contract.id);
+            }
         }
 
-        let ships = fleet_api::get_my_ships(configuration, None, None)
-            .await
-            .unwrap()
-            .data;
-        info!(
-            "Ships: {}",
-            ships
-                .iter()
-                .map(|s| s.symbol.to_owned())
-                .collect::<Vec<_>>()
-                .join(", ")
-        );
+        Step::PlanPurchases
+    }
 
-        let ship = fleet_api::get_my_ship(
-            configuration,
-            format!("{}-1", username.to_ascii_uppercase()).as_str(),
-        )
-        .await
-        .unwrap()
-        .data;
+    /// Works out how big a fleet the agent's credits buy at the home system's shipyard, taking
+    /// the command ship there when we need it present to see prices.
+    async fn plan_purchases(client: &Client) -> Step {
+        let ships = client.get_my_ships().await;
+        let ship = command_ship(&ships).expect("No command ship");
 
         let system = ship.nav.system_symbol.as_str();
         info!("[SETUP] Home system is {system}");
 
-        let waypoints =
-            systems_api::get_system_waypoints(configuration, system, None, None, None, None)
-                .await
-                .unwrap()
-                .data;
+        let waypoints = client.get_system_waypoints(system).await;
 
         let shipyard = waypoints.iter().find(|w| {
             w.traits
@@ -93,86 +297,104 @@ impl Setup {
         });
 
         let shipyard = match shipyard {
-            None => panic!(
-                "Failed to find shipyard in system {system}. Waypoints were: {}",
-                waypoints
-                    .iter()
-                    .map(|w| w.symbol.clone())
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            ),
-            Some(s) => s,
+            None => {
+                info!("[SETUP] No shipyard in {system}, starting with the command ship alone");
+                return Step::Deploy;
+            }
+            Some(s) => s.symbol.as_str(),
         };
+        info!("[SETUP] Found shipyard: {shipyard}");
 
-        info!("[SETUP] Found shipyard: {}", shipyard.symbol);
-
-        loop {
-            info!("[SETUP] Buying ship");
-
-            let purchase = fleet_api::purchase_ship(
-                configuration,
-                Some(PurchaseShipRequest::new(
-                    spacedust::models::ShipType::MiningDrone,
-                    shipyard.symbol.to_owned(),
-                )),
-            )
-            .await;
-
-            match purchase {
-                Result::Ok(_) => continue,
-                Result::Err(e) => {
-                    info!("[SETUP] Failed to buy ship, assuming out of money");
-                    dbg!(e);
-                    break;
-                }
-            }
+        if let Some(arrival) = client.depart(&ship.symbol, shipyard).await {
+            sleep_until(arrival).await;
         }
 
-        let asteroid_field = waypoints
+        let offers = client
+            .get_shipyard(system, shipyard)
+            .await
+            .ships
+            .unwrap_or_default();
+        let price = offers
             .iter()
-            .find(|w| w.r#type == WaypointType::AsteroidField)
-            .unwrap();
+            .find(|s| s.r#type == ShipType::MiningDrone)
+            .map(|s| s.purchase_price);
 
-        info!("[SETUP] Found asteroid field: {}", asteroid_field.symbol);
+        let price = match price {
+            None => {
+                info!("[SETUP] {shipyard} doesn't sell mining drones");
+                return Step::Deploy;
+            }
+            Some(p) => p,
+        };
 
-        let ships = fleet_api::get_my_ships(configuration, None, None)
-            .await
-            .unwrap()
-            .data;
+        let agent = client.get_my_agent().await;
+        let count = affordable(agent.credits, price);
+        info!(
+            "[SETUP] {} credits buy {count} mining drones at {price}",
+            agent.credits
+        );
 
-        if ships.len() == 1 {
-            panic!("We only have one ship ? Did we fail to buy any ?")
+        Step::Buying {
+            shipyard: shipyard.to_owned(),
+            fleet_size: agent.ship_count + count,
         }
+    }
 
-        let mut wait_seconds = None;
-        for s in ships {
-            info!(
-                "[SETUP] Navigating {} to {}",
-                s.symbol, asteroid_field.symbol
-            );
-            let nav = fleet_api::navigate_ship(
-                configuration,
-                s.symbol.as_str(),
-                Some(NavigateShipRequest::new(asteroid_field.symbol.to_string())),
-            )
-            .await
-            .unwrap()
-            .data;
+    /// Buys mining drones until the fleet has the planned size. Counting the ships we have rather
+    /// than the ones bought means a resumed run doesn't buy any twice.
+    async fn buy_ships(client: &Client, shipyard: &str, fleet_size: i32) -> Step {
+        loop {
+            let ship_count = client.get_my_agent().await.ship_count;
+            if ship_count >= fleet_size {
+                break;
+            }
 
-            let route = nav.nav.route;
-            let departure = DateTime::parse_from_rfc3339(route.departure_time.as_str()).unwrap();
-            let arrival = DateTime::parse_from_rfc3339(route.arrival.as_str()).unwrap();
+            info!("[SETUP] Buying ship {} of {fleet_size}", ship_count + 1);
+            let purchase = client.purchase_ship(ShipType::MiningDrone, shipyard).await;
 
-            let eta = (arrival - departure).num_seconds() as u64;
+            if let Result::Err(e) = purchase {
+                info!("[SETUP] Failed to buy ship, stopping at {ship_count}: {e}");
+                break;
+            }
+        }
+
+        Step::Deploy
+    }
+
+    /// Sends every ship to the home system's asteroid field and waits for them to get there.
+    async fn deploy(client: &Client) -> Step {
+        let ships = client.get_my_ships().await;
+        let system = command_ship(&ships)
+            .expect("No command ship")
+            .nav
+            .system_symbol
+            .as_str();
+
+        let waypoints = client.get_system_waypoints(system).await;
+
+        let asteroid_field = match waypoints
+            .iter()
+            .find(|w| w.r#type == WaypointType::AsteroidField)
+        {
+            None => {
+                info!("[SETUP] No asteroid field in {system}, leaving ships where they are");
+                return Step::Done;
+            }
+            Some(w) => w.symbol.as_str(),
+        };
+        info!("[SETUP] Found asteroid field: {asteroid_field}");
 
-            wait_seconds = wait_seconds.map_or(Some(eta), |v| Some(u64::max(v, eta)));
+        let mut last_arrival = None;
+        for ship in &ships {
+            let arrival = client.depart(&ship.symbol, asteroid_field).await;
+            last_arrival = last_arrival.max(arrival);
         }
 
-        let wait = wait_seconds.unwrap();
-        info!("[SETUP] Ships travelling to asteroid field, waiting {wait} seconds");
-        tokio::time::sleep(Duration::from_secs(wait)).await;
+        if let Some(arrival) = last_arrival {
+            sleep_until(arrival).await;
+        }
 
-        info!("[SETUP] Ready to go!");
+        Step::Done
     }
 }
 
@@ -189,7 +411,53 @@ mod tests {
 
         info!("user: {}", user);
 
-        Setup::setup_account(user.as_str()).await
+        // Registering writes credentials, which mustn't replace the real ones
+        let directory = tempfile::tempdir().unwrap();
+        let account = Account::in_directory(&user, directory.path().to_owned());
+        Setup::setup_account(&account, user.as_str(), Some(FactionSymbol::Cosmic)).await;
+    }
+
+    #[test]
+    fn buys_what_credits_afford_above_reserve() {
+        assert_eq!(affordable(175_000, 40_000), 4);
+        assert_eq!(affordable(5_000, 40_000), 0);
+        assert_eq!(affordable(175_000, 0), 0);
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let checkpoint = Checkpoint {
+            agent_symbol: "MXZ".into(),
+            reset_date: "2024-01-07".into(),
+            step: Step::Buying {
+                shipyard: "X1-ZA40-C3".into(),
+                fleet_size: 4,
+            },
+        };
+
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        assert_eq!(json, "{\"agentSymbol\":\"MXZ\",\"resetDate\":\"2024-01-07\",\"step\":{\"step\":\"buying\",\"shipyard\":\"X1-ZA40-C3\",\"fleetSize\":4}}");
+        assert_eq!(
+            serde_json::from_str::<Checkpoint>(&json).unwrap(),
+            checkpoint
+        );
+    }
+
+    #[test]
+    fn agent_symbols_are_validated() {
+        assert_eq!(validate_agent_symbol("mxz_2"), Ok("MXZ_2".to_owned()));
+        assert!(validate_agent_symbol("MX").is_err());
+        assert!(validate_agent_symbol("MXZ-TRADERS-2024").is_err());
+        assert!(validate_agent_symbol("MXZ 2").is_err());
+    }
+
+    #[test]
+    fn picks_recruiting_faction_with_preferred_traits() {
+        let factions: Vec<Faction> = serde_json::from_str("[{\"symbol\":\"COSMIC\",\"name\":\"Cosmic\",\"description\":\"\",\"headquarters\":\"X1-A\",\"isRecruiting\":true,\"traits\":[{\"symbol\":\"INNOVATIVE\",\"name\":\"\",\"description\":\"\"}]},{\"symbol\":\"VOID\",\"name\":\"Void\",\"description\":\"\",\"headquarters\":\"X1-B\",\"isRecruiting\":false,\"traits\":[{\"symbol\":\"CAPITALISTIC\",\"name\":\"\",\"description\":\"\"},{\"symbol\":\"COMMERCIAL\",\"name\":\"\",\"description\":\"\"}]},{\"symbol\":\"GALACTIC\",\"name\":\"Galactic\",\"description\":\"\",\"headquarters\":\"X1-C\",\"isRecruiting\":true,\"traits\":[{\"symbol\":\"FREE_MARKETS\",\"name\":\"\",\"description\":\"\"}]},{\"symbol\":\"QUANTUM\",\"name\":\"Quantum\",\"description\":\"\",\"headquarters\":\"X1-D\",\"isRecruiting\":true,\"traits\":[{\"symbol\":\"COMMERCIAL\",\"name\":\"\",\"description\":\"\"}]}]").unwrap();
+
+        assert_eq!(choose_faction(&factions), Some(FactionSymbol::Galactic));
+        assert_eq!(parse_faction("cosmic"), Some(FactionSymbol::Cosmic));
+        assert_eq!(parse_faction("NOPE"), None);
     }
 
     fn random_string() -> String {
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use spacedust::models::{ShipType, Shipyard, ShipyardShip};
+
+use crate::{
+    database::{self, Database},
+    purchasing::ShipOffer,
+};
+
+/// What we know about a shipyard. The ship types on sale are always visible, prices and specs
+/// only when one of our ships was present at the last refresh.
+#[derive(Clone, Debug)]
+pub struct ShipyardListing {
+    pub waypoint_symbol: String,
+    pub ship_types: Vec<ShipType>,
+    pub ships: Vec<ShipyardShip>,
+    pub prices_updated: Option<Instant>,
+}
+
+/// Catalogue of the shipyards we've seen, shared between every manager.
+#[derive(Clone, Default)]
+pub struct ShipyardIndex {
+    listings: Arc<Mutex<HashMap<String, ShipyardListing>>>,
+    database: Option<Database>,
+}
+
+impl ShipyardListing {
+    fn new(shipyard: &Shipyard, prices_updated: Option<Instant>) -> Self {
+        Self {
+            waypoint_symbol: shipyard.symbol.to_owned(),
+            ship_types: shipyard.ship_types.iter().map(|t| t.r#type).collect(),
+            ships: shipyard.ships.clone().unwrap_or_default(),
+            prices_updated,
+        }
+    }
+}
+
+impl ShipyardIndex {
+    /// Starts from the shipyards saved in the database and saves every shipyard recorded from
+    /// now on.
+    pub fn with_database(database: Database) -> Self {
+        let listings = database
+            .shipyards()
+            .into_iter()
+            .map(|(shipyard, updated)| {
+                let listing = ShipyardListing::new(&shipyard, updated.map(database::instant_at));
+                (shipyard.symbol, listing)
+            })
+            .collect();
+
+        Self {
+            listings: Arc::new(Mutex::new(listings)),
+            database: Some(database),
+        }
+    }
+
+    /// Records a `get_shipyard` response. Prices seen previously are kept when the response
+    /// doesn't include any because none of our ships is there anymore.
+    pub fn record(&self, shipyard: &Shipyard) {
+        let mut listings = self.listings.lock().unwrap();
+        let previous = listings.remove(&shipyard.symbol);
+
+        let mut shipyard = shipyard.clone();
+        let prices_updated = match &shipyard.ships {
+            Some(_) => Some(Instant::now()),
+            None => previous.and_then(|p| {
+                shipyard.ships = p.prices_updated.map(|_| p.ships);
+                p.prices_updated
+            }),
+        };
+
+        if let Some(database) = &self.database {
+            database.save_shipyard(&shipyard, prices_updated.map(database::datetime_at));
+        }
+
+        listings.insert(
+            shipyard.symbol.to_owned(),
+            ShipyardListing::new(&shipyard, prices_updated),
+        );
+    }
+
+    /// Every priced ship on sale in shipyards of the given system.
+    pub fn offers(&self, system_symbol: &str) -> Vec<ShipOffer> {
+        self.listings
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|l| in_system(&l.waypoint_symbol, system_symbol))
+            .flat_map(|l| {
+                l.ships.iter().map(|ship| ShipOffer {
+                    waypoint_symbol: l.waypoint_symbol.to_owned(),
+                    ship: ship.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn cheapest(&self, system_symbol: &str, ship_type: ShipType) -> Option<ShipOffer> {
+        self.offers(system_symbol)
+            .into_iter()
+            .filter(|o| o.ship.r#type == ship_type)
+            .min_by_key(|o| o.price())
+    }
+
+    /// Shipyards of the system listing the ship type, whether or not we know its price.
+    pub fn sellers(&self, system_symbol: &str, ship_type: ShipType) -> Vec<String> {
+        self.listings
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|l| in_system(&l.waypoint_symbol, system_symbol))
+            .filter(|l| l.ship_types.contains(&ship_type))
+            .map(|l| l.waypoint_symbol.to_owned())
+            .collect()
+    }
+}
+
+fn in_system(waypoint_symbol: &str, system_symbol: &str) -> bool {
+    waypoint_symbol
+        .strip_prefix(system_symbol)
+        .is_some_and(|rest| rest.starts_with('-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIPYARD: &str = "{\"symbol\":\"X1-ZA40-A2\",\"shipTypes\":[{\"type\":\"SHIP_MINING_DRONE\"},{\"type\":\"SHIP_PROBE\"}],\"modificationsFee\":100,\"ships\":[{\"type\":\"SHIP_MINING_DRONE\",\"name\":\"Mining Drone\",\"description\":\"\",\"supply\":\"MODERATE\",\"purchasePrice\":45000,\"frame\":{\"symbol\":\"FRAME_DRONE\",\"name\":\"Drone\",\"description\":\"\",\"moduleSlots\":3,\"mountingPoints\":2,\"fuelCapacity\":80,\"requirements\":{}},\"reactor\":{\"symbol\":\"REACTOR_CHEMICAL_I\",\"name\":\"Chemical Reactor I\",\"description\":\"\",\"powerOutput\":15,\"requirements\":{}},\"engine\":{\"symbol\":\"ENGINE_IMPULSE_DRIVE_I\",\"name\":\"Impulse Drive I\",\"description\":\"\",\"speed\":10,\"requirements\":{}},\"modules\":[],\"mounts\":[],\"crew\":{\"required\":0,\"capacity\":0}}]}";
+
+    #[test]
+    fn keeps_prices_when_no_ship_present() {
+        let index = ShipyardIndex::default();
+
+        let mut shipyard: Shipyard = serde_json::from_str(SHIPYARD).unwrap();
+        index.record(&shipyard);
+
+        shipyard.ships = None;
+        index.record(&shipyard);
+
+        let cheapest = index.cheapest("X1-ZA40", ShipType::MiningDrone).unwrap();
+        assert_eq!(cheapest.price(), 45000);
+        assert!(index.cheapest("X1-ZA40", ShipType::Probe).is_none());
+        assert_eq!(
+            index.sellers("X1-ZA40", ShipType::Probe),
+            vec!["X1-ZA40-A2".to_owned()]
+        );
+        assert!(index.sellers("X1-ZA4", ShipType::Probe).is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use log::info;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+
+/// Tells the tasks playing the agents that the process was asked to stop, so they can leave the
+/// ships in a state the next run resumes from.
+#[derive(Clone)]
+pub struct Shutdown {
+    receiver: watch::Receiver<bool>,
+}
+
+/// Asks the tasks of a scoped shutdown to stop, without stopping the rest of the process.
+#[derive(Clone)]
+pub struct Stop {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Stop {
+    pub fn request(&self) {
+        self.sender.send(true).ok();
+    }
+
+    /// Requests the stop once the guard is dropped, so the scope's tasks stop even when the task
+    /// owning it panics.
+    pub fn guard(&self) -> StopGuard {
+        StopGuard(self.clone())
+    }
+}
+
+pub struct StopGuard(Stop);
+
+impl Drop for StopGuard {
+    fn drop(&mut self) {
+        self.0.request();
+    }
+}
+
+/// Resolves on the next SIGINT or SIGTERM.
+async fn stop_signal() {
+    let mut interrupt = signal(SignalKind::interrupt()).unwrap();
+    let mut terminate = signal(SignalKind::terminate()).unwrap();
+
+    tokio::select! {
+        _ = interrupt.recv() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+impl Shutdown {
+    /// Starts listening for the signals asking the process to stop. A second signal exits right
+    /// away, for when shutting down gracefully takes too long.
+    pub fn listen() -> Self {
+        let (sender, receiver) = watch::channel(false);
+
+        tokio::spawn(async move {
+            stop_signal().await;
+            info!("[SHUTDOWN] Stopping once the ships finish what they are doing, signal again to exit now");
+            sender.send(true).ok();
+
+            stop_signal().await;
+            info!("[SHUTDOWN] Exiting now");
+            log::logger().flush();
+            std::process::exit(130);
+        });
+
+        Self { receiver }
+    }
+
+    /// A shutdown for part of the process, like one agent: requested along with this one, or on
+    /// its own with the returned `Stop`.
+    pub fn scope(&self) -> (Stop, Self) {
+        let (sender, receiver) = watch::channel(self.is_requested());
+        let stop = Stop {
+            sender: Arc::new(sender),
+        };
+
+        let (forward, mut parent) = (stop.clone(), self.clone());
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = parent.requested() => forward.request(),
+                // Everything in the scope is done
+                _ = forward.sender.closed() => {}
+            }
+        });
+
+        (stop, Self { receiver })
+    }
+
+    /// Whether the process, or the scope, was asked to stop.
+    pub fn is_requested(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolves once the process, or the scope, is asked to stop.
+    pub async fn requested(&mut self) {
+        // The sender lives as long as the process, so this only fails when it is exiting anyway
+        self.receiver.wait_for(|requested| *requested).await.ok();
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::info;
+
+use crate::behaviours::SavedBehaviour;
+
+/// Every ship's behaviour state, written to a JSON file after each transition so a restart
+/// resumes where the ships left off.
+#[derive(Clone)]
+pub struct StateStore {
+    path: PathBuf,
+    states: Arc<Mutex<HashMap<String, SavedBehaviour>>>,
+}
+
+impl StateStore {
+    /// Loads the states saved at `path`, starting empty if there are none or they can't be read.
+    pub fn load(path: PathBuf) -> Self {
+        let states = match fs::read_to_string(&path) {
+            Result::Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                info!("[STATES] Ignoring unreadable {}: {e}", path.display());
+                HashMap::new()
+            }),
+            Result::Err(_) => HashMap::new(),
+        };
+
+        Self {
+            path,
+            states: Arc::new(Mutex::new(states)),
+        }
+    }
+
+    pub fn get(&self, ship_symbol: &str) -> Option<SavedBehaviour> {
+        self.states.lock().unwrap().get(ship_symbol).cloned()
+    }
+
+    pub fn save(&self, ship_symbol: &str, state: SavedBehaviour) {
+        let mut states = self.states.lock().unwrap();
+        if states.get(ship_symbol) == Some(&state) {
+            return;
+        }
+
+        states.insert(ship_symbol.to_owned(), state);
+        self.write(&states);
+    }
+
+    pub fn remove(&self, ship_symbol: &str) {
+        let mut states = self.states.lock().unwrap();
+        if states.remove(ship_symbol).is_some() {
+            self.write(&states);
+        }
+    }
+
+    /// Forgets every state, moving the file into `directory` to keep a finished run's data
+    /// around.
+    pub fn archive(&self, directory: &Path) {
+        let mut states = self.states.lock().unwrap();
+        states.clear();
+
+        if let Some(name) = self.path.file_name() {
+            if let Err(e) = fs::rename(&self.path, directory.join(name)) {
+                info!("[STATES] Failed to archive {}: {e}", self.path.display());
+            }
+        }
+    }
+
+    /// Writes to a temporary file first so a crash mid-write doesn't lose every state.
+    fn write(&self, states: &HashMap<String, SavedBehaviour>) {
+        let temporary = self.path.with_extension("tmp");
+        let result = fs::write(&temporary, serde_json::to_string_pretty(states).unwrap())
+            .and_then(|_| fs::rename(&temporary, &self.path));
+
+        if let Err(e) = result {
+            info!("[STATES] Failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roles::Role;
+
+    #[test]
+    fn states_survive_reload() {
+        let path = std::env::temp_dir().join(format!("states-{}.json", std::process::id()));
+
+        let store = StateStore::load(path.clone());
+        store.save("A-1", SavedBehaviour::Role(Role::Hauler));
+        store.save("A-2", SavedBehaviour::Role(Role::Probe));
+        store.remove("A-2");
+
+        let reloaded = StateStore::load(path.clone());
+        assert_eq!(
+            reloaded.get("A-1"),
+            Some(SavedBehaviour::Role(Role::Hauler))
+        );
+        assert_eq!(reloaded.get("A-2"), None);
+
+        fs::remove_file(path).unwrap();
+    }
+}
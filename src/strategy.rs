@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{accounts::Account, configuration::parse_var};
+
+/// The strategy parameters that can be tuned while the fleet plays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrategySettings {
+    /// Credits kept aside when buying ships and mounts.
+    pub credit_reserve: i64,
+    /// Goods a market pays less per unit for are kept in the hold instead of sold.
+    pub sell_floor: i32,
+}
+
+/// Changes to the settings, leaving out the ones that stay.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrategyUpdate {
+    pub credit_reserve: Option<i64>,
+    pub sell_floor: Option<i32>,
+}
+
+/// An agent's strategy settings, shared by everything that plays it so changes take effect
+/// right away.
+#[derive(Clone)]
+pub struct Strategy {
+    settings: Arc<Mutex<StrategySettings>>,
+}
+
+impl Strategy {
+    pub fn new(settings: StrategySettings) -> Self {
+        Self {
+            settings: Arc::new(Mutex::new(settings)),
+        }
+    }
+
+    /// The settings from the agent's `CREDIT_RESERVE` and `SELL_FLOOR`.
+    pub fn from_env(account: &Account) -> Self {
+        let var = |key: &str| parse_var(key, account.var(key));
+
+        Self::new(StrategySettings {
+            credit_reserve: var("CREDIT_RESERVE").unwrap_or(20_000),
+            sell_floor: var("SELL_FLOOR").unwrap_or(0) as i32,
+        })
+    }
+
+    pub fn settings(&self) -> StrategySettings {
+        *self.settings.lock().unwrap()
+    }
+
+    /// Applies the changes, returning the settings now in effect.
+    pub fn update(&self, update: StrategyUpdate) -> StrategySettings {
+        let mut settings = self.settings.lock().unwrap();
+        if let Some(credit_reserve) = update.credit_reserve {
+            settings.credit_reserve = credit_reserve;
+        }
+        if let Some(sell_floor) = update.sell_floor {
+            settings.sell_floor = sell_floor;
+        }
+        *settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategy_updates_keep_what_is_left_out() {
+        let strategy = Strategy::new(StrategySettings {
+            credit_reserve: 20_000,
+            sell_floor: 0,
+        });
+        let update: StrategyUpdate = serde_json::from_str("{\"sellFloor\":35}").unwrap();
+
+        assert_eq!(
+            strategy.update(update),
+            StrategySettings {
+                credit_reserve: 20_000,
+                sell_floor: 35
+            }
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::{future::Future, time::Duration};
+
+use log::info;
+use tokio::{task::JoinError, time::Instant};
+
+use crate::shutdown::Shutdown;
+
+/// A task that ran this long before failing starts its backoff over.
+const HEALTHY_RUN: Duration = Duration::from_secs(600);
+
+/// How long to wait before restarting something that failed `failures` times in a row: 30
+/// seconds, doubling up to half an hour.
+pub fn backoff(failures: u32) -> Duration {
+    let seconds = 30u64.saturating_mul(1 << failures.saturating_sub(1).min(6));
+    Duration::from_secs(seconds.min(1800))
+}
+
+/// What a task panicked with, for the logs.
+pub fn panic_message(error: JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+
+    let panic = error.into_panic();
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or("unknown panic".to_owned()),
+    }
+}
+
+/// Runs the task started by `start` until it returns, starting it again after a backoff
+/// whenever it panics. Gives up on restarting it once shut down.
+pub async fn supervise<F, Fut>(name: &str, mut shutdown: Shutdown, mut start: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut failures = 0;
+
+    loop {
+        if shutdown.is_requested() {
+            return;
+        }
+
+        let started = Instant::now();
+        let Err(error) = tokio::spawn(start()).await else {
+            return;
+        };
+
+        if started.elapsed() >= HEALTHY_RUN {
+            failures = 0;
+        }
+        failures += 1;
+        let delay = backoff(failures);
+        info!(
+            "[SUPERVISOR] {name} failed: {}, restarting in {}s (failure {failures})",
+            panic_message(error),
+            delay.as_secs()
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.requested() => {
+                info!("[SUPERVISOR] Not restarting {name}, shutting down");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_half_an_hour() {
+        let delays: Vec<_> = (1..=8).map(|f| backoff(f).as_secs()).collect();
+        assert_eq!(delays, vec![30, 60, 120, 240, 480, 960, 1800, 1800]);
+    }
+}
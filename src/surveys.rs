@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use spacedust::models::Survey;
+
+use crate::database::Database;
+
+/// Surveys made by our surveyors, shared with the miners working the same waypoints.
+#[derive(Clone, Default)]
+pub struct SurveyPool {
+    surveys: Arc<Mutex<Vec<Survey>>>,
+    database: Option<Database>,
+}
+
+impl SurveyPool {
+    /// Starts from the unexpired surveys saved in the database and saves every survey added
+    /// from now on.
+    pub fn with_database(database: Database) -> Self {
+        Self {
+            surveys: Arc::new(Mutex::new(database.surveys())),
+            database: Some(database),
+        }
+    }
+
+    pub fn add(&self, surveys: Vec<Survey>) {
+        if let Some(database) = &self.database {
+            surveys.iter().for_each(|s| database.save_survey(s));
+        }
+        self.surveys.lock().unwrap().extend(surveys);
+    }
+
+    /// The largest unexpired survey of the waypoint, dropping the expired ones.
+    pub fn best(&self, waypoint_symbol: &str) -> Option<Survey> {
+        let mut surveys = self.surveys.lock().unwrap();
+        let now = Utc::now();
+        surveys.retain(|s| DateTime::parse_from_rfc3339(&s.expiration).is_ok_and(|e| e > now));
+
+        surveys
+            .iter()
+            .filter(|s| s.symbol == waypoint_symbol)
+            .max_by_key(|s| s.size)
+            .cloned()
+    }
+
+    /// Forgets a survey that got exhausted.
+    pub fn remove(&self, signature: &str) {
+        if let Some(database) = &self.database {
+            database.remove_survey(signature);
+        }
+        self.surveys
+            .lock()
+            .unwrap()
+            .retain(|s| s.signature != signature);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn survey(signature: &str, waypoint_symbol: &str, size: &str, expiration: &str) -> Survey {
+        serde_json::from_str(&format!("{{\"signature\":\"{signature}\",\"symbol\":\"{waypoint_symbol}\",\"deposits\":[{{\"symbol\":\"IRON_ORE\"}}],\"expiration\":\"{expiration}\",\"size\":\"{size}\"}}")).unwrap()
+    }
+
+    #[test]
+    fn best_prefers_large_unexpired_surveys() {
+        let pool = SurveyPool::default();
+        pool.add(vec![
+            survey("A", "X1-ZA40-B7", "SMALL", "2999-01-01T00:00:00.000Z"),
+            survey("B", "X1-ZA40-B7", "LARGE", "2000-01-01T00:00:00.000Z"),
+            survey("C", "X1-ZA40-B7", "MODERATE", "2999-01-01T00:00:00.000Z"),
+            survey("D", "X1-ZA40-C1", "LARGE", "2999-01-01T00:00:00.000Z"),
+        ]);
+
+        assert_eq!(pool.best("X1-ZA40-B7").unwrap().signature, "C");
+
+        pool.remove("C");
+        assert_eq!(pool.best("X1-ZA40-B7").unwrap().signature, "A");
+    }
+}
@@ -0,0 +1,18 @@
+use log::info;
+
+use crate::{control::Control, dashboard::Dashboard, events, ledger::Ledgers, metrics};
+
+/// Serves the dashboard on `/`, the metrics on `/metrics`, the event logs on `/api/events` and
+/// the profit and loss reports on `/api/ledger` and the control API on `/api/agents`.
+pub async fn serve(address: &str, dashboard: Dashboard, control: Control, ledgers: Ledgers) {
+    let app = dashboard
+        .routes()
+        .merge(metrics::routes())
+        .merge(events::routes())
+        .merge(ledgers.routes())
+        .merge(control.routes());
+    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+
+    info!("[WEB] Serving the dashboard on http://{address}/");
+    axum::serve(listener, app).await.unwrap();
+}